@@ -0,0 +1,66 @@
+//! Lookups across a 200 frame tree.
+//!
+//! `lookup_transform 200 frames` compared with the buffer before frame ids were interned, at
+//! commit 6d7dc58, running the same tree and lookup. These are the criterion point estimates in
+//! ns of six alternating runs of `cargo bench`, the last three with `--measurement-time 10`. They
+//! ran on a single core machine with a lot of noise between runs:
+//!
+//! | buffer              | runs                           | median |
+//! |---------------------|--------------------------------|--------|
+//! | 6d7dc58, String ids | 539, 411, 494, 494, 334, 385   | 452    |
+//! | interned `FrameId`s | 317, 413, 394, 308, 361, 337   | 349    |
+//!
+//! The interned buffer is faster by about a fifth at the median, but single runs overlap.
+use criterion::{
+    black_box,
+    criterion_group,
+    criterion_main,
+    Criterion
+};
+
+use rosrust_tf2::msg;
+use rosrust_tf2::time;
+use rosrust_tf2::time::Time;
+use rosrust_tf2::{
+    TfBuffer,
+    TransformInterface
+};
+
+// The fixtures of the unit tests, they find `msg` and `time` through the imports above
+#[allow(dead_code)]
+#[path = "../src/test_utils.rs"]
+mod test_utils;
+
+use test_utils::make_transform;
+
+
+const FRAME_COUNT: usize = 200;
+
+/// Builds a binary tree of `FRAME_COUNT` frames sampled at two stamps so that lookups interpolate.
+fn build_tree() -> TfBuffer {
+    let mut buffer = TfBuffer::new();
+    for sec in 0..2 {
+        let transforms = (1..FRAME_COUNT)
+            .map(|i| make_transform(&format!("frame_{}", (i - 1) / 2), &format!("frame_{}", i), sec, 1f64, std::f64::consts::FRAC_PI_4))
+            .collect();
+        buffer.handle_incoming_transforms(msg::TFMessage{transforms}, false, "bench").unwrap();
+    }
+    buffer
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let buffer = build_tree();
+    let time = Time{sec: 0, nsec: 500_000_000};
+    let source = format!("frame_{}", FRAME_COUNT - 1);
+    let target = format!("frame_{}", FRAME_COUNT / 2 - 1);
+
+    c.bench_function("lookup_transform 200 frames", |b| b.iter(|| {
+        buffer.lookup_transform(black_box(&target), black_box(&source), time).unwrap()
+    }));
+    c.bench_function("lookup_isometry 200 frames", |b| b.iter(|| {
+        buffer.lookup_isometry(black_box(&target), black_box(&source), time).unwrap()
+    }));
+}
+
+criterion_group!(benches, bench_lookup);
+criterion_main!(benches);
@@ -0,0 +1,174 @@
+// Generates testdata/interpolation_golden.csv with tf2's own BufferCore.
+//
+// Build and run it from the root of the repository with a sourced ROS 1 install:
+//
+//   ROS=/opt/ros/$ROS_DISTRO
+//   g++ -std=c++14 -O2 scripts/generate_interpolation_golden.cpp -I$ROS/include -L$ROS/lib -Wl,-rpath,$ROS/lib -ltf2 -lrostime -lcpp_common -o /tmp/generate_interpolation_golden
+//   /tmp/generate_interpolation_golden > testdata/interpolation_golden.csv
+//
+// Each row holds two stamped samples of the transform from "parent" to "child", a query stamp
+// and the transform tf2 looks up at that stamp:
+// stamp1,x1,y1,z1,qx1,qy1,qz1,qw1,stamp2,x2,...,qw2,query,x,y,z,qx,qy,qz,qw
+// Stamps are in nanoseconds.
+#include <cmath>
+#include <cstdint>
+#include <cstdio>
+#include <functional>
+#include <vector>
+
+#include <geometry_msgs/TransformStamped.h>
+#include <ros/time.h>
+#include <tf2/buffer_core.h>
+
+namespace
+{
+
+const int64_t SEC = 1000000000;
+
+// BufferCore reads a stamp of zero as "the latest transform", the samples are handed to it
+// shifted by this offset. Interpolation only depends on the differences of the stamps.
+const int64_t STAMP_OFFSET = 1000 * SEC;
+
+struct Sample
+{
+  int64_t stamp;
+  double translation[3];
+  double rotation[4];
+};
+
+void quatFromAxisAngle(const double axis[3], double angle, double rotation[4])
+{
+  double norm = std::sqrt(axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2]);
+  double s = std::sin(angle / 2.0);
+  rotation[0] = axis[0] / norm * s;
+  rotation[1] = axis[1] / norm * s;
+  rotation[2] = axis[2] / norm * s;
+  rotation[3] = std::cos(angle / 2.0);
+}
+
+Sample rotating(const double axis[3], double rate, int64_t stamp)
+{
+  Sample sample{stamp, {0.0, 0.0, 0.0}, {}};
+  quatFromAxisAngle(axis, rate * stamp / SEC, sample.rotation);
+  return sample;
+}
+
+Sample translating(const double velocity[3], int64_t stamp)
+{
+  Sample sample{stamp, {}, {0.0, 0.0, 0.0, 1.0}};
+  for (int i = 0; i < 3; ++i)
+  {
+    sample.translation[i] = velocity[i] * stamp / SEC;
+  }
+  return sample;
+}
+
+Sample screw(int64_t stamp)
+{
+  const double z_axis[3] = {0.0, 0.0, 1.0};
+  double angle = 0.8 * stamp / SEC;
+  Sample sample{stamp, {std::cos(angle), std::sin(angle), 0.3 * stamp / SEC}, {}};
+  quatFromAxisAngle(z_axis, angle, sample.rotation);
+  return sample;
+}
+
+geometry_msgs::TransformStamped toMessage(const Sample& sample)
+{
+  geometry_msgs::TransformStamped message;
+  message.header.frame_id = "parent";
+  message.header.stamp.fromNSec(sample.stamp + STAMP_OFFSET);
+  message.child_frame_id = "child";
+  message.transform.translation.x = sample.translation[0];
+  message.transform.translation.y = sample.translation[1];
+  message.transform.translation.z = sample.translation[2];
+  message.transform.rotation.x = sample.rotation[0];
+  message.transform.rotation.y = sample.rotation[1];
+  message.transform.rotation.z = sample.rotation[2];
+  message.transform.rotation.w = sample.rotation[3];
+  return message;
+}
+
+void printSample(const Sample& sample)
+{
+  std::printf("%lld,%.17g,%.17g,%.17g,%.17g,%.17g,%.17g,%.17g,", static_cast<long long>(sample.stamp),
+              sample.translation[0], sample.translation[1], sample.translation[2],
+              sample.rotation[0], sample.rotation[1], sample.rotation[2], sample.rotation[3]);
+}
+
+void writeRow(const Sample& sample1, const Sample& sample2, int64_t query)
+{
+  tf2::BufferCore buffer;
+  buffer.setTransform(toMessage(sample1), "golden");
+  buffer.setTransform(toMessage(sample2), "golden");
+  ros::Time time;
+  time.fromNSec(query + STAMP_OFFSET);
+  geometry_msgs::TransformStamped result = buffer.lookupTransform("parent", "child", time);
+
+  printSample(sample1);
+  printSample(sample2);
+  const geometry_msgs::Vector3& v = result.transform.translation;
+  const geometry_msgs::Quaternion& q = result.transform.rotation;
+  std::printf("%lld,%.17g,%.17g,%.17g,%.17g,%.17g,%.17g,%.17g\n", static_cast<long long>(query),
+              v.x, v.y, v.z, q.x, q.y, q.z, q.w);
+}
+
+int64_t queryStamp(int64_t start, double query)
+{
+  return start + static_cast<int64_t>(std::llround(query * SEC));
+}
+
+}  // namespace
+
+int main()
+{
+  const std::vector<double> queries = {0.0, 0.1, 0.25, 0.4999, 0.5, 0.5001, 0.75, 0.9, 1.0};
+  const double z_axis[3] = {0.0, 0.0, 1.0};
+  const double skewed_axis[3] = {1.0, -2.0, 0.5};
+  const double y_axis[3] = {0.0, 1.0, 0.0};
+  const double velocity[3] = {1.0, -0.5, 2.0};
+  const std::vector<std::function<Sample(int64_t)>> trajectories = {
+    [&](int64_t stamp) { return rotating(z_axis, 1.0, stamp); },
+    [&](int64_t stamp) { return rotating(skewed_axis, 2.5, stamp); },
+    [&](int64_t stamp) { return rotating(y_axis, 3.0, stamp); },
+    [&](int64_t stamp) { return translating(velocity, stamp); },
+    screw,
+  };
+
+  std::printf("# Generated by scripts/generate_interpolation_golden.cpp, do not edit\n");
+  for (const auto& trajectory : trajectories)
+  {
+    for (int64_t start : {int64_t(0), SEC, 3 * SEC + 250000000})
+    {
+      Sample sample1 = trajectory(start);
+      Sample sample2 = trajectory(start + SEC);
+      for (double query : queries)
+      {
+        writeRow(sample1, sample2, queryStamp(start, query));
+      }
+    }
+  }
+
+  // Both samples hold the same rotation with opposite quaternion signs
+  Sample sample1{0, {0.0, 0.0, 0.0}, {}};
+  quatFromAxisAngle(z_axis, 0.5, sample1.rotation);
+  Sample sample2{SEC, {2.0, 0.0, 0.0}, {}};
+  for (int i = 0; i < 4; ++i)
+  {
+    sample2.rotation[i] = -sample1.rotation[i];
+  }
+  for (double query : queries)
+  {
+    writeRow(sample1, sample2, queryStamp(0, query));
+  }
+
+  // Rotations more than pi apart along the unsigned quaternion path
+  sample1 = Sample{0, {0.0, 0.0, 0.0}, {}};
+  quatFromAxisAngle(z_axis, 0.1, sample1.rotation);
+  sample2 = Sample{SEC, {0.0, 0.0, 0.0}, {}};
+  quatFromAxisAngle(z_axis, 2 * M_PI - 0.3, sample2.rotation);
+  for (double query : queries)
+  {
+    writeRow(sample1, sample2, queryStamp(0, query));
+  }
+  return 0;
+}
@@ -0,0 +1,205 @@
+#!/usr/bin/env python3
+"""Generates the MCAP fixtures of testdata/, laid out like the recordings of rosbag2 and of the
+MCAP ROS1 recorder.
+
+The files are written from the MCAP specification (https://mcap.dev/spec), independently of the
+writer of the crate. Chunks are compressed with the zstd and lz4 command line tools, which must be
+on the PATH. Each file holds a chunk of /tf_static and a chunk of /tf followed by their message
+indexes, then a summary section with the schemas, channels, statistics and chunk indexes.
+
+* tf_cdr_zstd.mcap: ros2 profile, tf2_msgs/msg/TFMessage in CDR, zstd chunks.
+* tf_ros1_lz4.mcap: ros1 profile, tf2_msgs/TFMessage in the ROS1 wire format, lz4 chunks, with the
+  publishing node in the callerid metadata of the channels.
+
+Both hold the same transforms:
+* /tf_static, logged at 1s: base_link -> camera, translation (0.1, 0, 0.3), rotation (0, 0, 0, 1)
+* /tf, logged at 1s, 1.5s and 2s, stamped 1s, 1.5s and 2s: odom -> base_link, translation
+  (t - 1, 0, 0) and a rotation of 0.2 * (t - 1) radians around z, t being the stamp in seconds.
+"""
+import math
+import struct
+import subprocess
+import zlib
+
+MAGIC = b"\x89MCAP0\r\n"
+OP_HEADER = 0x01
+OP_FOOTER = 0x02
+OP_SCHEMA = 0x03
+OP_CHANNEL = 0x04
+OP_MESSAGE = 0x05
+OP_CHUNK = 0x06
+OP_MESSAGE_INDEX = 0x07
+OP_CHUNK_INDEX = 0x08
+OP_STATISTICS = 0x0B
+OP_DATA_END = 0x0F
+SEC = 1_000_000_000
+
+# The reader does not parse definitions, only the first line of both is kept.
+TF_MESSAGE_DEFINITION = b"geometry_msgs/TransformStamped[] transforms\n"
+
+
+def string(value):
+    value = value.encode() if isinstance(value, str) else value
+    return struct.pack("<I", len(value)) + value
+
+
+def string_map(entries):
+    content = b"".join(string(key) + string(value) for key, value in entries)
+    return struct.pack("<I", len(content)) + content
+
+
+def record(opcode, content):
+    return struct.pack("<BQ", opcode, len(content)) + content
+
+
+def static_transforms():
+    return [(1 * SEC, "base_link", "camera", (0.1, 0.0, 0.3), (0.0, 0.0, 0.0, 1.0))]
+
+
+def dynamic_transforms():
+    for stamp in (1 * SEC, 3 * SEC // 2, 2 * SEC):
+        t = stamp / SEC - 1.0
+        yield stamp, "odom", "base_link", (t, 0.0, 0.0), (0.0, 0.0, math.sin(0.1 * t), math.cos(0.1 * t))
+
+
+class CdrWriter:
+    def __init__(self):
+        self.data = bytearray(b"\x00\x01\x00\x00")
+
+    def align(self, size):
+        while (len(self.data) - 4) % size:
+            self.data.append(0)
+
+    def u32(self, value):
+        self.align(4)
+        self.data += struct.pack("<I", value)
+
+    def f64(self, value):
+        self.align(8)
+        self.data += struct.pack("<d", value)
+
+    def string(self, value):
+        self.u32(len(value) + 1)
+        self.data += value.encode() + b"\x00"
+
+
+def encode_cdr(transforms):
+    writer = CdrWriter()
+    writer.u32(len(transforms))
+    for stamp, parent, child, translation, rotation in transforms:
+        writer.u32(stamp // SEC)
+        writer.u32(stamp % SEC)
+        writer.string(parent)
+        writer.string(child)
+        for value in translation + rotation:
+            writer.f64(value)
+    return bytes(writer.data)
+
+
+def encode_ros1(transforms):
+    data = struct.pack("<I", len(transforms))
+    for seq, (stamp, parent, child, translation, rotation) in enumerate(transforms):
+        data += struct.pack("<III", seq, stamp // SEC, stamp % SEC)
+        data += string(parent) + string(child)
+        data += struct.pack("<7d", *(translation + rotation))
+    return data
+
+
+def compress(compression, data):
+    command = {"zstd": ["zstd", "-q", "-c", "-19"], "lz4": ["lz4", "-q", "-c", "-9"]}[compression]
+    return subprocess.run(command, input=data, stdout=subprocess.PIPE, check=True).stdout
+
+
+def write_fixture(path, profile, schema, channels, encode, compression):
+    """`channels` maps the id of each channel to its topic, metadata and messages, as
+    (log_time, transforms) pairs."""
+    out = bytearray(MAGIC)
+    out += record(OP_HEADER, string(profile) + string("generate_mcap_fixtures.py"))
+
+    schema_id, schema_name, schema_encoding, schema_data = schema
+    schema_record = record(OP_SCHEMA, struct.pack("<H", schema_id) + string(schema_name)
+                           + string(schema_encoding) + string(schema_data))
+    channel_records = {}
+    for channel_id, (topic, message_encoding, metadata, _) in channels.items():
+        channel_records[channel_id] = record(OP_CHANNEL, struct.pack("<HH", channel_id, schema_id) + string(topic)
+                                             + string(message_encoding) + string_map(metadata))
+
+    chunk_indexes = []
+    sequence = 0
+    message_count = 0
+    channel_message_counts = {}
+    for chunk_number, (channel_id, (_, _, _, messages)) in enumerate(channels.items()):
+        records = bytearray()
+        if chunk_number == 0:
+            records += schema_record
+        records += channel_records[channel_id]
+        offsets = []
+        for log_time, transforms in messages:
+            offsets.append((log_time, len(records)))
+            records += record(OP_MESSAGE, struct.pack("<HIQQ", channel_id, sequence, log_time, log_time)
+                              + encode(transforms))
+            sequence += 1
+        message_count += len(messages)
+        channel_message_counts[channel_id] = len(messages)
+
+        compressed = compress(compression, bytes(records))
+        start_time = min(log_time for log_time, _ in messages)
+        end_time = max(log_time for log_time, _ in messages)
+        chunk = struct.pack("<QQQI", start_time, end_time, len(records), zlib.crc32(records))
+        chunk += string(compression) + struct.pack("<Q", len(compressed)) + compressed
+        chunk_start = len(out)
+        out += record(OP_CHUNK, chunk)
+        chunk_length = len(out) - chunk_start
+
+        index_start = len(out)
+        entries = b"".join(struct.pack("<QQ", log_time, offset) for log_time, offset in offsets)
+        out += record(OP_MESSAGE_INDEX, struct.pack("<HI", channel_id, len(entries)) + entries)
+        chunk_indexes.append((start_time, end_time, chunk_start, chunk_length, channel_id, index_start,
+                              len(out) - index_start, compression, len(compressed), len(records)))
+
+    out += record(OP_DATA_END, struct.pack("<I", zlib.crc32(out)))
+
+    summary_start = len(out)
+    out += schema_record
+    for channel_record in channel_records.values():
+        out += channel_record
+    counts = b"".join(struct.pack("<HQ", channel_id, count) for channel_id, count in channel_message_counts.items())
+    out += record(OP_STATISTICS, struct.pack("<QHIIIIQQ", message_count, 1, len(channels), 0, 0, len(chunk_indexes),
+                                             min(index[0] for index in chunk_indexes),
+                                             max(index[1] for index in chunk_indexes))
+                  + struct.pack("<I", len(counts)) + counts)
+    for (start_time, end_time, chunk_start, chunk_length, channel_id, index_start, index_length,
+         compression, compressed_size, uncompressed_size) in chunk_indexes:
+        offsets = struct.pack("<HQ", channel_id, index_start)
+        out += record(OP_CHUNK_INDEX, struct.pack("<QQQQ", start_time, end_time, chunk_start, chunk_length)
+                      + struct.pack("<I", len(offsets)) + offsets
+                      + struct.pack("<Q", index_length) + string(compression)
+                      + struct.pack("<QQ", compressed_size, uncompressed_size))
+
+    # The summary CRC covers the summary section and the start of the footer.
+    footer_start = struct.pack("<BQQQ", OP_FOOTER, 20, summary_start, 0)
+    out += footer_start + struct.pack("<I", zlib.crc32(out[summary_start:] + footer_start))
+    out += MAGIC
+    with open(path, "wb") as fixture:
+        fixture.write(out)
+
+
+def main():
+    static_messages = [(1 * SEC, static_transforms())]
+    dynamic_messages = [(transform[0], [transform]) for transform in dynamic_transforms()]
+    qos = [("offered_qos_profiles", "- history: 3\n  depth: 0\n  reliability: 1\n  durability: 1\n")]
+
+    write_fixture("testdata/tf_cdr_zstd.mcap", "ros2",
+                  (1, "tf2_msgs/msg/TFMessage", "ros2msg", TF_MESSAGE_DEFINITION),
+                  {1: ("/tf_static", "cdr", qos, static_messages),
+                   2: ("/tf", "cdr", [], dynamic_messages)},
+                  encode_cdr, "zstd")
+    write_fixture("testdata/tf_ros1_lz4.mcap", "ros1",
+                  (1, "tf2_msgs/TFMessage", "ros1msg", TF_MESSAGE_DEFINITION),
+                  {1: ("/tf_static", "ros1", [("callerid", "/robot_state_publisher"), ("latching", "1")], static_messages),
+                   2: ("/tf", "ros1", [("callerid", "/odometry")], dynamic_messages)},
+                  encode_ros1, "lz4")
+
+
+if __name__ == "__main__":
+    main()
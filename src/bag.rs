@@ -0,0 +1,240 @@
+//! Reading of the transforms recorded in ROS1 bag files.
+//!
+//! Only the parts of the [bag format 2.0](http://wiki.ros.org/Bags/Format/2.0) needed to replay
+//! `/tf` and `/tf_static` are supported: records are read in file order and chunks have to be
+//! uncompressed, compressed bags can be converted with `rosbag decompress`.
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+
+use rosrust::RosMsg;
+
+use crate::buffer::TfBuffer;
+use crate::msg;
+use crate::time::Time;
+
+
+const BAG_MAGIC: &[u8] = b"#ROSBAG V2.0\n";
+const OP_MESSAGE_DATA: u8 = 0x02;
+const OP_CHUNK: u8 = 0x05;
+const OP_CONNECTION: u8 = 0x07;
+
+pub const TF_TOPIC: &str = "/tf";
+pub const TF_STATIC_TOPIC: &str = "/tf_static";
+
+/// A message of `/tf` or `/tf_static` recorded in a bag.
+#[derive(Clone, Debug)]
+pub struct BagTfMessage {
+    pub topic: String,
+    /// The node that published the message.
+    pub authority: String,
+    /// The time the message was recorded at.
+    pub time: Time,
+    pub message: msg::TFMessage
+}
+
+impl BagTfMessage {
+    pub fn is_static(&self) -> bool {
+        self.topic == TF_STATIC_TOPIC
+    }
+}
+
+struct Connection {
+    topic: String,
+    authority: String
+}
+
+/// Reads the messages of `/tf` and `/tf_static` recorded in a bag, in file order.
+pub fn read_tf_messages<R: Read>(mut reader: R) -> io::Result<Vec<BagTfMessage>> {
+    let mut magic = [0u8; 13];
+    reader.read_exact(&mut magic)?;
+    if magic != BAG_MAGIC {
+        return Err(invalid_data("not a ROS bag in format 2.0".to_string()));
+    }
+    let mut connections = HashMap::new();
+    let mut messages = Vec::new();
+    read_records(&mut reader, &mut connections, &mut messages)?;
+    Ok(messages)
+}
+
+impl TfBuffer {
+    /// Builds a buffer from the transforms recorded in a bag. Transforms the buffer refuses are
+    /// skipped, see `rejected_count`.
+    pub fn from_bag<R: Read>(reader: R) -> io::Result<TfBuffer> {
+        let mut buffer = TfBuffer::new();
+        for message in read_tf_messages(reader)? {
+            let static_tf = message.is_static();
+            let _ = buffer.handle_incoming_transforms(message.message, static_tf, &message.authority);
+        }
+        Ok(buffer)
+    }
+}
+
+fn read_records<R: Read>(reader: &mut R, connections: &mut HashMap<u32, Connection>, messages: &mut Vec<BagTfMessage>) -> io::Result<()> {
+    while let Some(header) = read_block(reader)? {
+        let data = read_block(reader)?.ok_or_else(|| invalid_data("truncated record".to_string()))?;
+        let header = parse_fields(&header)?;
+        match field(&header, "op")? {
+            [OP_CHUNK] => match field(&header, "compression")? {
+                b"none" => read_records(&mut data.as_slice(), connections, messages)?,
+                compression => return Err(invalid_data(
+                    format!("unsupported chunk compression {}", String::from_utf8_lossy(compression))))
+            },
+            [OP_CONNECTION] => {
+                let topic = String::from_utf8_lossy(field(&header, "topic")?).into_owned();
+                if topic != TF_TOPIC && topic != TF_STATIC_TOPIC {
+                    continue;
+                }
+                let connection_header = parse_fields(&data)?;
+                let authority = connection_header.get("callerid")
+                    .map(|callerid| String::from_utf8_lossy(callerid).into_owned())
+                    .unwrap_or_default();
+                connections.insert(u32::decode(field(&header, "conn")?)?, Connection{topic, authority});
+            },
+            [OP_MESSAGE_DATA] => {
+                if let Some(connection) = connections.get(&u32::decode(field(&header, "conn")?)?) {
+                    messages.push(BagTfMessage {
+                        topic: connection.topic.clone(),
+                        authority: connection.authority.clone(),
+                        time: Time::decode(field(&header, "time")?)?,
+                        message: msg::TFMessage::decode(data.as_slice())?
+                    });
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Reads a block prefixed by its length, returns None at the end of the input.
+fn read_block<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0u8; 4];
+    match reader.read_exact(&mut length) {
+        Ok(()) => {},
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err)
+    }
+    // The length is not trusted with an allocation, the block grows as it is read
+    let mut block = Vec::new();
+    let length = u32::from_le_bytes(length);
+    reader.take(u64::from(length)).read_to_end(&mut block)?;
+    if block.len() as u64 != u64::from(length) {
+        return Err(invalid_data("truncated block".to_string()));
+    }
+    Ok(Some(block))
+}
+
+/// Parses the `name=value` fields of a record or connection header.
+fn parse_fields(mut header: &[u8]) -> io::Result<HashMap<&str, &[u8]>> {
+    let mut fields = HashMap::new();
+    while !header.is_empty() {
+        let length = u32::decode(&mut header)? as usize;
+        if length > header.len() {
+            return Err(invalid_data("truncated header field".to_string()));
+        }
+        let (field, rest) = header.split_at(length);
+        header = rest;
+        let separator = field.iter().position(|byte| *byte == b'=')
+            .ok_or_else(|| invalid_data("header field without =".to_string()))?;
+        let name = std::str::from_utf8(&field[..separator])
+            .map_err(|_| invalid_data("header field name is not UTF-8".to_string()))?;
+        fields.insert(name, &field[separator + 1..]);
+    }
+    Ok(fields)
+}
+
+fn field<'a>(fields: &HashMap<&str, &'a [u8]>, name: &str) -> io::Result<&'a [u8]> {
+    fields.get(name).copied().ok_or_else(|| invalid_data(format!("record without {} field", name)))
+}
+
+fn invalid_data(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_message;
+
+    fn encode_fields(fields: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        for (name, value) in fields {
+            encoded.extend_from_slice(&((name.len() + 1 + value.len()) as u32).to_le_bytes());
+            encoded.extend_from_slice(name.as_bytes());
+            encoded.push(b'=');
+            encoded.extend_from_slice(value);
+        }
+        encoded
+    }
+
+    fn encode_record(fields: &[(&str, &[u8])], data: &[u8]) -> Vec<u8> {
+        let header = encode_fields(fields);
+        let mut record = Vec::new();
+        record.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        record.extend_from_slice(&header);
+        record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        record.extend_from_slice(data);
+        record
+    }
+
+    fn encode_connection(conn: u32, topic: &str, callerid: &str) -> Vec<u8> {
+        let connection_header = encode_fields(&[("topic", topic.as_bytes()), ("type", b"tf2_msgs/TFMessage"), ("callerid", callerid.as_bytes())]);
+        encode_record(&[("op", &[OP_CONNECTION]), ("conn", &conn.to_le_bytes()), ("topic", topic.as_bytes())], &connection_header)
+    }
+
+    fn encode_message(conn: u32, sec: u32, data: &[u8]) -> Vec<u8> {
+        let mut time = sec.to_le_bytes().to_vec();
+        time.extend_from_slice(&0u32.to_le_bytes());
+        encode_record(&[("op", &[OP_MESSAGE_DATA]), ("conn", &conn.to_le_bytes()), ("time", &time)], data)
+    }
+
+    fn encode_bag(compression: &str, records: &[Vec<u8>]) -> Vec<u8> {
+        let mut bag = BAG_MAGIC.to_vec();
+        bag.extend(encode_record(&[("op", &[0x03]), ("conn_count", &2u32.to_le_bytes()), ("chunk_count", &1u32.to_le_bytes())], &[b' '; 16]));
+        bag.extend(encode_record(&[("op", &[OP_CHUNK]), ("compression", compression.as_bytes())], &records.concat()));
+        bag
+    }
+
+    /// Tests that the tf messages of a bag are decoded and the other topics skipped
+    #[test]
+    fn test_read_tf_messages() {
+        let static_message = make_message("base_link", "camera", 0, 0.5, 0.5);
+        let dynamic_message = make_message("odom", "base_link", 7, 2f64, 0.5);
+        let mut static_data = Vec::new();
+        static_message.encode(&mut static_data).unwrap();
+        let mut dynamic_data = Vec::new();
+        dynamic_message.encode(&mut dynamic_data).unwrap();
+
+        let bag = encode_bag("none", &[
+            encode_connection(0, TF_STATIC_TOPIC, "/robot_state_publisher"),
+            encode_connection(1, "/rosout", "/rosout"),
+            encode_connection(2, TF_TOPIC, "/odometry"),
+            encode_message(0, 1, &static_data),
+            encode_message(1, 2, b"not a tf message"),
+            encode_message(2, 7, &dynamic_data)
+        ]);
+        let messages = read_tf_messages(bag.as_slice()).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].is_static());
+        assert_eq!(messages[0].authority, "/robot_state_publisher");
+        assert_eq!(messages[0].time, Time{sec: 1, nsec: 0});
+        assert_eq!(messages[0].message, static_message);
+        assert!(!messages[1].is_static());
+        assert_eq!(messages[1].message, dynamic_message);
+
+        let buffer = TfBuffer::from_bag(bag.as_slice()).unwrap();
+        assert_eq!(buffer.frames(), vec!("base_link", "camera", "odom"));
+        assert_eq!(buffer.frame_authority("base_link"), Some("/odometry"));
+
+        let compressed = encode_bag("bz2", &[encode_connection(0, TF_TOPIC, "/odometry")]);
+        assert_eq!(read_tf_messages(compressed.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(read_tf_messages(&b"#ROSBAG V1.2\n"[..]).is_err());
+
+        // A block announcing more bytes than the bag holds is refused without allocating them
+        let mut truncated = BAG_MAGIC.to_vec();
+        truncated.extend_from_slice(&u32::MAX.to_le_bytes());
+        truncated.extend_from_slice(b"op");
+        assert_eq!(read_tf_messages(truncated.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,82 @@
+//! Compares the frame trees recorded in two bags, MCAP files or snapshots.
+//!
+//! Exits with 0 when the trees match, 1 when they differ and 2 on error, like `diff`.
+use std::env;
+use std::path::Path;
+use std::process;
+
+use rosrust_tf2::TfBuffer;
+use rosrust_tf2::diff::DiffTolerances;
+
+
+const USAGE: &str = "usage: tf_diff [--translation-tolerance METERS] [--rotation-tolerance RADIANS] OLD NEW
+
+OLD and NEW are ROS bags (.bag), MCAP files (.mcap) or snapshots written by TfBuffer::to_snapshot.";
+
+fn load_buffer(path: &str) -> Result<TfBuffer, String> {
+    if Path::new(path).extension().is_some_and(|extension| extension == "bag") {
+        return load_bag(path);
+    }
+    if Path::new(path).extension().is_some_and(|extension| extension == "mcap") {
+        let file = std::fs::File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+        return TfBuffer::from_mcap(std::io::BufReader::new(file)).map_err(|err| format!("{}: {}", path, err));
+    }
+    let snapshot = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    TfBuffer::from_snapshot(&snapshot).map_err(|err| format!("{}: {:?}", path, err))
+}
+
+#[cfg(feature = "ros1")]
+fn load_bag(path: &str) -> Result<TfBuffer, String> {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    let file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+    TfBuffer::from_bag(BufReader::new(file)).map_err(|err| format!("{}: {}", path, err))
+}
+
+#[cfg(not(feature = "ros1"))]
+fn load_bag(path: &str) -> Result<TfBuffer, String> {
+    Err(format!("{}: reading bags requires the ros1 feature", path))
+}
+
+fn parse_tolerance(name: &str, value: Option<String>) -> Result<f64, String> {
+    value.and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{} expects a number", name))
+}
+
+fn run() -> Result<bool, String> {
+    let mut tolerances = DiffTolerances::default();
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--translation-tolerance" => tolerances.translation = parse_tolerance(&arg, args.next())?,
+            "--rotation-tolerance" => tolerances.rotation = parse_tolerance(&arg, args.next())?,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(true);
+            },
+            _ => paths.push(arg)
+        }
+    }
+    if paths.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+
+    let old = load_buffer(&paths[0])?;
+    let new = load_buffer(&paths[1])?;
+    let diff = old.diff_with_tolerances(&new, &tolerances);
+    print!("{}", diff);
+    Ok(diff.is_empty())
+}
+
+fn main() {
+    match run() {
+        Ok(true) => {},
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    }
+}
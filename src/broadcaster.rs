@@ -0,0 +1,89 @@
+use crate::msg;
+use crate::namespace::qualify_message;
+
+
+/// Publishes transforms on `/tf` and `/tf_static` with rosrust.
+///
+/// With a `tf_prefix`, the frame ids of the published transforms are resolved like
+/// `NamespacedTfBuffer` resolves them: `base_link` is sent as `<prefix>/base_link` and `/map` as
+/// `map`, so a robot can broadcast its frames next to the ones of the rest of the fleet.
+pub struct TfBroadcaster {
+    prefix: String,
+    publisher: rosrust::Publisher<msg::TFMessage>,
+    static_publisher: rosrust::Publisher<msg::TFMessage>,
+    static_transforms: Vec<msg::TransformStamped>
+}
+
+impl TfBroadcaster {
+    /// Advertises `/tf` and `/tf_static`, frame ids are sent as they are given. ROS has to be
+    /// initialized.
+    pub fn new() -> rosrust::error::Result<Self> {
+        Self::with_prefix("")
+    }
+
+    /// Advertises `/tf` and `/tf_static`, resolving frame ids against `prefix`.
+    pub fn with_prefix(prefix: &str) -> rosrust::error::Result<Self> {
+        let publisher = rosrust::publish("/tf", 100)?;
+        let mut static_publisher = rosrust::publish("/tf_static", 100)?;
+        static_publisher.set_latching(true);
+        Ok(TfBroadcaster {
+            prefix: prefix.trim_matches('/').to_string(),
+            publisher,
+            static_publisher,
+            static_transforms: Vec::new()
+        })
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn send_transforms(&self, transforms: Vec<msg::TransformStamped>) -> rosrust::error::Result<()> {
+        self.publisher.send(qualify_message(&self.prefix, msg::TFMessage{transforms}))
+    }
+
+    /// Publishes static transforms.
+    ///
+    /// Only the last message of `/tf_static` is kept for late subscribers, so like tf2's static
+    /// broadcaster every message carries all the static transforms sent so far, the last value of
+    /// a child frame replacing the previous ones.
+    pub fn send_static_transforms(&mut self, transforms: Vec<msg::TransformStamped>) -> rosrust::error::Result<()> {
+        let message = qualify_message(&self.prefix, msg::TFMessage{transforms});
+        merge_static_transforms(&mut self.static_transforms, message.transforms);
+        self.static_publisher.send(msg::TFMessage{transforms: self.static_transforms.clone()})
+    }
+}
+
+/// Adds static transforms to the ones already sent, replacing those of the same child frame.
+fn merge_static_transforms(sent: &mut Vec<msg::TransformStamped>, transforms: Vec<msg::TransformStamped>) {
+    for transform in transforms {
+        match sent.iter_mut().find(|sent| sent.child_frame_id == transform.child_frame_id) {
+            Some(sent) => *sent = transform,
+            None => sent.push(transform)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_transform;
+
+    /// Tests that static messages carry every static transform sent, prefixed, with their last
+    /// value
+    #[test]
+    fn test_static_transforms() {
+        let mut sent = Vec::new();
+        let qualify = |transforms| qualify_message("robot1", msg::TFMessage{transforms}).transforms;
+        merge_static_transforms(&mut sent, qualify(vec!(make_transform("base_link", "camera", 0, 1f64, 0f64))));
+        merge_static_transforms(&mut sent, qualify(vec!(
+            make_transform("/map", "odom", 0, 2f64, 0f64),
+            make_transform("base_link", "robot1/camera", 0, 3f64, 0f64)
+        )));
+
+        assert_eq!(sent, vec!(
+            make_transform("robot1/base_link", "robot1/camera", 0, 3f64, 0f64),
+            make_transform("map", "robot1/odom", 0, 2f64, 0f64)
+        ));
+    }
+}
@@ -1,196 +1,770 @@
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::collections::VecDeque;
 use std::collections::HashSet; 
+use std::sync::{
+    Arc,
+    RwLock
+};
 
-use rosrust;
+use nalgebra::base::Matrix6;
+use nalgebra::geometry;
 
 use crate::core::{
     TransformInterface,
     TransformWithTimeInterface,
     TfError
 };
-use crate::transforms;
-use crate::graph::TfGraphNode;
-use crate::chain::TfIndividualTransformChain;
+use crate::graph::{
+    TfGraphNode,
+    PathCache,
+    TransformPath
+};
+use crate::chain::{
+    TfIndividualTransformChain,
+    TfStaticTransform
+};
+use crate::clock::{
+    poll_until,
+    Clock
+};
+use crate::covariance::compose_covariance;
+use crate::events::{
+    EventHandlers,
+    SubscriptionId,
+    TfEvent
+};
+use crate::frame::{
+    FrameId,
+    FrameInterner
+};
 use crate::utils::{
     get_inverse,
-    to_transform_stamped
+    to_transform_stamped,
+    validate_frame_id,
+    validate_transform_values
 };
 use crate::msg;
+use crate::time::{
+    Duration,
+    Time
+};
 
 
 const _DEFAULT_CACHE_TIME: i32 = 10;
-const _MAX_GRAPH_DEPTH: u32 = 1000;
+const MAX_GRAPH_DEPTH: usize = 1000;
 
 
+/// An edge of the tree as stored in the buffer.
+pub(crate) struct TreeEdge<'a> {
+    pub parent_frame: &'a str,
+    pub child_frame: &'a str,
+    /// The single value of a static edge, or the history of a dynamic one, oldest first.
+    pub transforms: &'a [msg::TransformStamped],
+    pub static_tf: bool,
+    pub authority: &'a str
+}
+
 #[derive(Clone, Debug)]
 pub struct TfBuffer {
-    child_transform_index: HashMap<String, HashSet<String> >,
-    transform_data: HashMap<TfGraphNode, TfIndividualTransformChain>
+    frames: FrameInterner,
+    child_transform_index: HashMap<FrameId, HashSet<FrameId> >,
+    parent_index: HashMap<FrameId, FrameId>,
+    transform_data: HashMap<TfGraphNode, TfIndividualTransformChain>,
+    static_transform_data: HashMap<TfGraphNode, TfStaticTransform>,
+    edge_covariances: HashMap<TfGraphNode, Matrix6<f64>>,
+    /// Keyed by the names of the parent and child frames, refused frames are not interned.
+    rejected_transforms: HashMap<(String, String), usize>,
+    path_cache: PathCache,
+    detect_time_jumps: bool,
+    last_update: Option<Time>,
+    event_handlers: EventHandlers,
+    clock: Arc<dyn Clock>
+}
+
+impl Default for TfBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TfBuffer {
 
+    /// Creates an empty buffer that follows the clock of the ROS node, or the system clock
+    /// without the `ros1` feature.
     pub fn new() -> TfBuffer {
-        TfBuffer{child_transform_index: HashMap::new(), transform_data: HashMap::new()}
+        #[cfg(feature = "ros1")]
+        let clock = Arc::new(crate::clock::RosClock);
+        #[cfg(not(feature = "ros1"))]
+        let clock = Arc::new(crate::clock::SystemClock);
+        Self::with_clock(clock)
+    }
+
+    /// Creates an empty buffer that reads the current time from `clock`.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> TfBuffer {
+        TfBuffer{
+            frames: FrameInterner::default(),
+            child_transform_index: HashMap::new(),
+            parent_index: HashMap::new(),
+            transform_data: HashMap::new(),
+            static_transform_data: HashMap::new(),
+            edge_covariances: HashMap::new(),
+            rejected_transforms: HashMap::new(),
+            path_cache: PathCache::default(),
+            detect_time_jumps: false,
+            last_update: None,
+            event_handlers: EventHandlers::default(),
+            clock
+        }
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Registers a callback called with every change of the buffer, as the change is made.
+    ///
+    /// Callbacks run on the thread modifying the buffer and must not block it.
+    pub fn subscribe<F>(&mut self, callback: F) -> SubscriptionId
+    where F: Fn(&TfEvent) + Send + Sync + 'static {
+        self.event_handlers.subscribe(Arc::new(callback))
+    }
+
+    /// Removes a callback registered with `subscribe`. Returns false if it was already removed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.event_handlers.unsubscribe(id)
+    }
+
+    /// Drops every transform from the buffer.
+    pub fn clear(&mut self) {
+        let frames: Vec<FrameId> = self.child_transform_index.keys().copied().collect();
+        for frame in frames {
+            self.event_handlers.emit(|| TfEvent::FrameRemoved{frame_id: self.frames.name(frame).to_string()});
+        }
+        self.frames = FrameInterner::default();
+        self.child_transform_index.clear();
+        self.parent_index.clear();
+        self.transform_data.clear();
+        self.static_transform_data.clear();
+        self.edge_covariances.clear();
+        self.rejected_transforms.clear();
+        self.path_cache.clear();
+        self.last_update = None;
+    }
+
+    /// Drops the dynamic transforms from the buffer, the static ones are kept. Frames left without
+    /// any transform leave the tree.
+    pub fn clear_dynamic(&mut self) {
+        let edges: Vec<TfGraphNode> = self.transform_data.keys().copied().collect();
+        for edge in &edges {
+            self.remove_edge(edge.parent, edge.child);
+            if self.parent_index.get(&edge.child) == Some(&edge.parent) {
+                self.parent_index.remove(&edge.child);
+            }
+        }
+        let mut orphans: Vec<FrameId> = edges.into_iter()
+            .map(|edge| edge.child)
+            .filter(|frame| !self.child_transform_index.contains_key(frame))
+            .collect();
+        orphans.sort();
+        orphans.dedup();
+        self.forget_frames(orphans);
+    }
+
+    /// Drops a frame along with all the transforms from and to it. The children of the frame are
+    /// left without a parent, those that had no other transform leave the tree as well.
+    ///
+    /// Returns false if the frame is not in the buffer.
+    pub fn remove_frame(&mut self, frame_id: &str) -> bool {
+        let frame = match validate_frame_id(frame_id).ok().and_then(|frame_id| self.frames.get(frame_id)) {
+            Some(frame) => frame,
+            None => return false
+        };
+        let neighbours = match self.child_transform_index.remove(&frame) {
+            Some(neighbours) => neighbours,
+            None => return false
+        };
+        for neighbour in &neighbours {
+            self.remove_edge(frame, *neighbour);
+            self.remove_edge(*neighbour, frame);
+        }
+        self.parent_index.remove(&frame);
+        self.parent_index.retain(|_, parent| *parent != frame);
+        self.path_cache.clear();
+
+        let orphans = neighbours.into_iter().filter(|neighbour| !self.child_transform_index.contains_key(neighbour));
+        let removed = std::iter::once(frame).chain(orphans).collect();
+        self.forget_frames(removed);
+        true
+    }
+
+    /// Drops every trace of frames that have left the tree: their rejection counts and their id,
+    /// and reports their removal to the subscribers.
+    fn forget_frames(&mut self, frames: Vec<FrameId>) {
+        for frame in frames {
+            let name = self.frames.name(frame).to_string();
+            self.rejected_transforms.retain(|(parent, child), _| *parent != name && *child != name);
+            self.event_handlers.emit(|| TfEvent::FrameRemoved{frame_id: name.clone()});
+            self.frames.forget(frame);
+        }
     }
 
-    pub fn handle_incoming_transforms(&mut self, transforms: msg::TFMessage, static_tf: bool) {
+    /// Enables or disables the detection of jumps back in time, see `update_time`. Disabled by
+    /// default.
+    ///
+    /// When enabled, `handle_incoming_transforms` reads the clock of the buffer on every call.
+    pub fn set_time_jump_detection(&mut self, enabled: bool) {
+        self.detect_time_jumps = enabled;
+    }
+
+    /// Tells the buffer the current time, meant to be called whenever transforms are received.
+    ///
+    /// When time jump detection is enabled and `now` is earlier than the previous update, as
+    /// happens when simulation time is reset or a bag loops, the dynamic transforms are cleared
+    /// like tf2's `TransformListener` does. Returns true if a jump was detected.
+    pub fn update_time(&mut self, now: Time) -> bool {
+        let jumped = self.detect_time_jumps && self.last_update.is_some_and(|last_update| now < last_update);
+        if jumped {
+            log::warn!("Detected a jump back in time to {}, clearing the dynamic transforms", now);
+            self.clear_dynamic();
+        }
+        self.last_update = Some(now);
+        jumped
+    }
+
+    /// Adds the transforms of a message to the buffer, `authority` being the node that published
+    /// them.
+    ///
+    /// Transforms with invalid frame ids or that would break the tree are refused while the rest
+    /// of the message is still inserted. The error of the first refused transform is returned.
+    pub fn handle_incoming_transforms(&mut self, transforms: msg::TFMessage, static_tf: bool, authority: &str) -> Result<(), TfError> {
+        if self.detect_time_jumps {
+            self.update_time(self.clock.now());
+        }
+        let mut result = Ok(());
         for transform in transforms.transforms {
+            let (parent_frame, child_frame) = (transform.header.frame_id.clone(), transform.child_frame_id.clone());
+            let mut transform = match Self::validate_transform(transform) {
+                Ok(transform) => transform,
+                Err(error) => {
+                    self.event_handlers.emit(|| TfEvent::MessageRejected{parent_frame, child_frame, error: error.clone()});
+                    result = result.and(Err(error));
+                    continue;
+                }
+            };
+            // Checked before anything is recorded so that a refused transform leaves no trace
+            if let Err(error) = validate_transform_values(&mut transform.transform) {
+                self.reject_transform(&transform, &error);
+                result = result.and(Err(error));
+                continue;
+            }
+            let parent = self.frames.intern(&transform.header.frame_id);
+            let child = self.frames.intern(&transform.child_frame_id);
+            // Checked before reparenting, which briefly takes the child out of the tree
+            let known_parent = self.child_transform_index.contains_key(&parent);
+            let known_child = self.child_transform_index.contains_key(&child);
+            let previous_authority = self.parent_authority(child).map(str::to_string);
+            if let Err(error) = self.update_parent(parent, child, authority) {
+                self.reject_transform(&transform, &error);
+                result = result.and(Err(error));
+                continue;
+            }
+            self.check_authority(child, previous_authority.as_deref(), authority);
+            for (frame, known) in [(parent, known_parent), (child, known_child)] {
+                if !known {
+                    self.event_handlers.emit(|| TfEvent::FrameAdded{frame_id: self.frames.name(frame).to_string()});
+                }
+            }
             let inverse_transform = get_inverse(transform.clone());
-            self.add_transform(transform, static_tf);
-            self.add_transform(inverse_transform, static_tf);
+            self.add_transform(transform, static_tf, authority);
+            self.add_transform(inverse_transform, static_tf, authority);
         }
+        result
+    }
+
+    /// Counts a refused transform and reports it to the subscribers.
+    fn reject_transform(&mut self, transform: &msg::TransformStamped, error: &TfError) {
+        let (parent_frame, child_frame) = (transform.header.frame_id.clone(), transform.child_frame_id.clone());
+        *self.rejected_transforms.entry((parent_frame.clone(), child_frame.clone())).or_default() += 1;
+        self.event_handlers.emit(|| TfEvent::MessageRejected{parent_frame, child_frame, error: error.clone()});
+    }
+
+    /// Returns how many transforms from `parent_frame` to `child_frame` have been refused.
+    pub fn rejected_count(&self, parent_frame: &str, child_frame: &str) -> usize {
+        self.rejected_transforms.get(&(parent_frame.to_string(), child_frame.to_string())).copied().unwrap_or(0)
+    }
+
+    /// Lists the ids of all the frames known to the buffer, sorted by name.
+    pub fn frames(&self) -> Vec<String> {
+        let mut frames: Vec<String> = self.child_transform_index.keys()
+            .map(|frame| self.frames.name(*frame).to_string())
+            .collect();
+        frames.sort();
+        frames
+    }
+
+    /// Returns the node that last published the transform from `frame_id` to its parent.
+    pub fn frame_authority(&self, frame_id: &str) -> Option<&str> {
+        let child = self.frames.get(validate_frame_id(frame_id).ok()?)?;
+        self.parent_authority(child)
     }
 
-    fn add_transform(&mut self, transform: msg::TransformStamped, static_tf: bool) {
-        //TODO: Detect is new transform will create a loop
-        if self.child_transform_index.contains_key(&transform.header.frame_id) {
-            let res = self.child_transform_index.get_mut(&transform.header.frame_id.clone()).unwrap();
-            res.insert(transform.child_frame_id.clone());
+    /// Describes every frame of the tree in the YAML format of tf2's `allFramesAsYAML`: its
+    /// parent, the node broadcasting it, the publishing rate and the time span of its history.
+    /// Static frames are reported with a rate of 10000 and no history, as tf2 does.
+    pub fn all_frames_as_yaml(&self) -> String {
+        let mut yaml = String::new();
+        for edge in self.tree_edges() {
+            let (length, oldest, latest) = match (edge.static_tf, edge.transforms.first(), edge.transforms.last()) {
+                (false, Some(oldest), Some(latest)) => (edge.transforms.len(), oldest.header.stamp.seconds(), latest.header.stamp.seconds()),
+                _ => (1, 0f64, 0f64)
+            };
+            let rate = length as f64 / (latest - oldest).max(0.0001);
+            yaml.push_str(&format!("{}: \n", edge.child_frame));
+            yaml.push_str(&format!("  parent: '{}'\n", edge.parent_frame));
+            yaml.push_str(&format!("  broadcaster: '{}'\n", edge.authority));
+            yaml.push_str(&format!("  rate: {:.3}\n", rate));
+            yaml.push_str(&format!("  most_recent_transform: {:.3}\n", latest));
+            yaml.push_str(&format!("  oldest_transform: {:.3}\n", oldest));
+            yaml.push_str(&format!("  buffer_length: {:.3}\n", latest - oldest));
         }
-        else {
-            self.child_transform_index.insert(transform.header.frame_id.clone(), HashSet::new());
-            let res = self.child_transform_index.get_mut(&transform.header.frame_id.clone()).unwrap();
-            res.insert(transform.child_frame_id.clone());
+        yaml
+    }
+
+    /// Lists the edges of the tree, from parent to child, sorted by child frame.
+    pub(crate) fn tree_edges(&self) -> Vec<TreeEdge<'_>> {
+        let mut edges: Vec<TreeEdge> = self.parent_index.iter()
+            .filter_map(|(child, parent)| {
+                let edge = TfGraphNode{child: *child, parent: *parent};
+                let (transforms, static_tf, authority) = match (self.static_transform_data.get(&edge), self.transform_data.get(&edge)) {
+                    (Some(static_transform), _) => (std::slice::from_ref(static_transform.transform()), true, static_transform.authority()),
+                    (None, Some(chain)) => (chain.transforms(), false, chain.authority()),
+                    (None, None) => return None
+                };
+                Some(TreeEdge {
+                    parent_frame: self.frames.name(*parent),
+                    child_frame: self.frames.name(*child),
+                    transforms,
+                    static_tf,
+                    authority
+                })
+            })
+            .collect();
+        edges.sort_by_key(|edge| edge.child_frame);
+        edges
+    }
+
+    fn parent_authority(&self, child: FrameId) -> Option<&str> {
+        let edge = TfGraphNode{child, parent: *self.parent_index.get(&child)?};
+        match self.static_transform_data.get(&edge) {
+            Some(static_transform) => Some(static_transform.authority()),
+            None => self.transform_data.get(&edge).map(TfIndividualTransformChain::authority)
         }
+    }
+
+    /// Warns when `child` is published by another node than `previous_authority`, the one that
+    /// last published it.
+    fn check_authority(&self, child: FrameId, previous_authority: Option<&str>, authority: &str) {
+        if let Some(previous_authority) = previous_authority {
+            if previous_authority != authority {
+                log::warn!("Frame {} is published by both {} and {}",
+                    self.frames.name(child), previous_authority, authority);
+                self.event_handlers.emit(|| TfEvent::AuthorityConflict{
+                    frame_id: self.frames.name(child).to_string(),
+                    previous_authority: previous_authority.to_string(),
+                    authority: authority.to_string()
+                });
+            }
+        }
+    }
+
+    /// Normalizes the frame ids of an incoming transform and refuses self-referential ones.
+    fn validate_transform(mut transform: msg::TransformStamped) -> Result<msg::TransformStamped, TfError> {
+        transform.header.frame_id = validate_frame_id(&transform.header.frame_id)?.to_string();
+        transform.child_frame_id = validate_frame_id(&transform.child_frame_id)?.to_string();
+        if transform.header.frame_id == transform.child_frame_id {
+            return Err(TfError::InvalidArgument(
+                format!("transform from {} to itself", transform.child_frame_id)));
+        }
+        Ok(transform)
+    }
+
+    /// Records `parent` as the parent of `child`.
+    ///
+    /// A frame has a single parent: like tf2, the most recent publisher wins and the edge to the
+    /// previous parent is dropped. A parent that is a descendant of `child` would close a loop and
+    /// is refused. `authority` is the node publishing the new parent.
+    fn update_parent(&mut self, parent: FrameId, child: FrameId, authority: &str) -> Result<(), TfError> {
+        let old_parent = self.parent_index.get(&child).copied();
+        if old_parent == Some(parent) {
+            return Ok(());
+        }
+
+        let mut ancestor = Some(parent);
+        while let Some(frame) = ancestor {
+            if frame == child {
+                return Err(TfError::LoopDetected(format!("adding {} as parent of {} would create a loop",
+                    self.frames.name(parent), self.frames.name(child))));
+            }
+            ancestor = self.parent_index.get(&frame).copied();
+        }
+
+        if let Some(old_parent) = old_parent {
+            log::warn!("Frame {} is reparented from {} published by {} to {} published by {}",
+                self.frames.name(child), self.frames.name(old_parent), self.parent_authority(child).unwrap_or_default(),
+                self.frames.name(parent), authority);
+            self.remove_edge(old_parent, child);
+            self.remove_edge(child, old_parent);
+            self.event_handlers.emit(|| TfEvent::FrameReparented{
+                frame_id: self.frames.name(child).to_string(),
+                old_parent: self.frames.name(old_parent).to_string(),
+                new_parent: self.frames.name(parent).to_string()
+            });
+        }
+        self.parent_index.insert(child, parent);
+        Ok(())
+    }
+
+    fn remove_edge(&mut self, parent: FrameId, child: FrameId) {
+        if let Entry::Occupied(mut children) = self.child_transform_index.entry(parent) {
+            children.get_mut().remove(&child);
+            if children.get().is_empty() {
+                children.remove();
+            }
+        }
+        self.transform_data.remove(&TfGraphNode{child, parent});
+        self.static_transform_data.remove(&TfGraphNode{child, parent});
+        self.edge_covariances.remove(&TfGraphNode{child, parent});
+        self.path_cache.clear();
+    }
+
+    fn add_transform(&mut self, transform: msg::TransformStamped, static_tf: bool, authority: &str) {
+        let parent = self.frames.intern(&transform.header.frame_id);
+        let child = self.frames.intern(&transform.child_frame_id);
+        let new_edge = self.child_transform_index
+            .entry(parent)
+            .or_default()
+            .insert(child);
+        if new_edge {
+            self.path_cache.clear();
+        }
+
+        // Edges are stored both ways, only the changes of the edges of the tree are reported
+        let tree_edge = self.parent_index.get(&child) == Some(&parent);
         
-        let key = TfGraphNode{child: transform.child_frame_id.clone(), parent: transform.header.frame_id.clone()};
-        
-        if self.transform_data.contains_key(&key) {
-            let data = self.transform_data.get_mut(&key).unwrap();
-            data.add_to_buffer(transform.clone());
+        // An edge is either static or dynamic, the kind it was last published as wins
+        let edge = TfGraphNode{child, parent};
+        if static_tf {
+            self.transform_data.remove(&edge);
+            match self.static_transform_data.entry(edge) {
+                Entry::Occupied(mut entry) => {
+                    entry.get_mut().update(transform, authority);
+                    if tree_edge {
+                        self.event_handlers.emit(|| TfEvent::StaticTransformUpdated{
+                            parent_frame: self.frames.name(parent).to_string(),
+                            child_frame: self.frames.name(child).to_string()
+                        });
+                    }
+                },
+                Entry::Vacant(entry) => {
+                    entry.insert(TfStaticTransform::new(transform, authority));
+                }
+            }
         }
         else {
-            let mut data = TfIndividualTransformChain::new(static_tf);
-            data.add_to_buffer(transform.clone());
-            self.transform_data.insert(key, data);
+            self.static_transform_data.remove(&edge);
+            let pruned = self.transform_data.entry(edge).or_default().add_to_buffer(transform, authority);
+            if let (Some(stamp), true) = (pruned, tree_edge) {
+                self.event_handlers.emit(|| TfEvent::CachePruned{
+                    parent_frame: self.frames.name(parent).to_string(),
+                    child_frame: self.frames.name(child).to_string(),
+                    stamp
+                });
+            }
         }
     }
  
-    /// Retrieves the transform path
-    fn retrieve_transform_path(&self, from: String, to: String) -> Result<Vec<String>, TfError> {
-        let mut res = vec!();
-        let mut frontier: VecDeque<String> = VecDeque::new();
-        let mut visited: HashSet<String> = HashSet::new();
-        let mut parents: HashMap<String, String> = HashMap::new();
-        visited.insert(from.clone());
-        frontier.push_front(from.clone());
-
-        while !frontier.is_empty() {
-            let current_node = frontier.pop_front().unwrap();
+    /// Retrieves the frames on the shortest path from `from` to `to`, `from` excluded.
+    fn retrieve_transform_path(&self, from: FrameId, to: FrameId) -> Result<TransformPath, TfError> {
+        if let Some(path) = self.path_cache.get(from, to) {
+            return Ok(path);
+        }
+
+        let mut frontier: VecDeque<(FrameId, usize)> = VecDeque::new();
+        let mut parents: HashMap<FrameId, FrameId> = HashMap::new();
+        let mut depth_exceeded = false;
+        frontier.push_back((from, 0));
+
+        while let Some((current_node, depth)) = frontier.pop_front() {
             if current_node == to {
                 break;
             }
-            let children = self.child_transform_index.get(&current_node);
-            match children {
-                Some(children) => {
-                    for  v in children {
-                        if visited.contains(&v.to_string()) {
-                            continue;
-                        }
-                        parents.insert(v.to_string(), current_node.clone());
-                        frontier.push_front(v.to_string()); 
-                        visited.insert(v.to_string());  
-                    } 
-                },
-                None => {}
+            let children = match self.child_transform_index.get(&current_node) {
+                Some(children) => children,
+                None => continue
+            };
+            if depth >= MAX_GRAPH_DEPTH {
+                depth_exceeded = true;
+                continue;
+            }
+            for child in children {
+                if *child == from || parents.contains_key(child) {
+                    continue;
+                }
+                parents.insert(*child, current_node);
+                frontier.push_back((*child, depth + 1));
             }
-            
         }
+
+        let mut res = vec!();
         let mut r = to;
         while r != from {
-            res.push(r.clone());
-            let parent = parents.get(&r);
-            
-            match parent {
-                Some(x) => {
-                    r = x.to_string()
-                },
-                None => return Err(TfError::CouldNotFindTransform) 
+            res.push(r);
+            match parents.get(&r) {
+                Some(parent) => r = *parent,
+                None if depth_exceeded => return Err(TfError::MaxDepthExceeded),
+                None => return Err(TfError::CouldNotFindTransform)
             }
         }
         res.reverse();
+        let res: TransformPath = res.into();
+        self.path_cache.insert(from, to, res.clone());
         Ok(res)
     }
+
+    /// Returns the transform of an edge of the tree at a given time.
+    fn edge_isometry(&self, edge: &TfGraphNode, time: Time) -> Result<geometry::Isometry3<f64>, TfError> {
+        match self.static_transform_data.get(edge) {
+            Some(static_transform) => Ok(static_transform.get_isometry()),
+            None => self.transform_data.get(edge).unwrap().get_closest_isometry(time)
+        }
+    }
+
+    /// Approximate number of bytes used to store the static transforms.
+    pub fn static_memory_usage(&self) -> usize {
+        self.static_transform_data.values().map(TfStaticTransform::memory_usage).sum()
+    }
+
+    /// Approximate number of bytes used to store the history of the dynamic transforms.
+    pub fn dynamic_memory_usage(&self) -> usize {
+        self.transform_data.values().map(TfIndividualTransformChain::memory_usage).sum()
+    }
+
+    /// Looks up a transform within the tree at a given time and returns it as an SE3 matrix.
+    pub fn lookup_isometry(&self, target_frame: &str, source_frame: &str, time: Time) -> Result<geometry::Isometry3<f64>, TfError> {
+        self.chain_path(validate_frame_id(target_frame)?, validate_frame_id(source_frame)?, time)
+    }
+
+    /// Attaches a covariance to the transform from `parent_frame` to `child_frame`.
+    ///
+    /// The covariance is taken over left perturbations of the transform, ordered as
+    /// (linear, angular) and expressed in `parent_frame`. It is kept until the edge is removed.
+    pub fn set_transform_covariance(&mut self, parent_frame: &str, child_frame: &str, covariance: Matrix6<f64>) -> Result<(), TfError> {
+        let parent = self.frames.get(validate_frame_id(parent_frame)?).ok_or(TfError::CouldNotFindTransform)?;
+        let child = self.frames.get(validate_frame_id(child_frame)?).ok_or(TfError::CouldNotFindTransform)?;
+        if self.parent_index.get(&child) != Some(&parent) {
+            return Err(TfError::CouldNotFindTransform);
+        }
+        self.edge_covariances.insert(TfGraphNode{child, parent}, covariance);
+        Ok(())
+    }
+
+    /// Looks up a transform along with its covariance, propagated to first order from the
+    /// covariances of the edges on the path. Edges without a covariance are taken as exact.
+    ///
+    /// The covariance is over left perturbations of the returned transform and expressed in
+    /// `target_frame`.
+    pub fn lookup_transform_with_covariance(&self, target_frame: &str, source_frame: &str, time: Time) -> Result<(msg::TransformStamped, Matrix6<f64>), TfError> {
+        let target_frame = validate_frame_id(target_frame)?;
+        let source_frame = validate_frame_id(source_frame)?;
+        let mut isometry = geometry::Isometry3::identity();
+        let mut covariance = Matrix6::zeros();
+        if target_frame != source_frame {
+            let target = self.frames.get(target_frame).ok_or(TfError::CouldNotFindTransform)?;
+            let source = self.frames.get(source_frame).ok_or(TfError::CouldNotFindTransform)?;
+            let path = self.retrieve_transform_path(target, source)?;
+
+            let mut parent = target;
+            for child in path.iter() {
+                let edge = TfGraphNode{child: *child, parent};
+                let edge_isometry = self.edge_isometry(&edge, time)?;
+                let edge_covariance = match self.edge_covariances.get(&edge) {
+                    Some(edge_covariance) => Some(*edge_covariance),
+                    None => self.edge_covariances
+                        .get(&TfGraphNode{child: parent, parent: *child})
+                        .map(|inverse_covariance| compose_covariance(&Matrix6::zeros(), &edge_isometry, inverse_covariance))
+                };
+                if let Some(edge_covariance) = edge_covariance {
+                    covariance = compose_covariance(&covariance, &isometry, &edge_covariance);
+                }
+                isometry *= edge_isometry;
+                parent = *child;
+            }
+        }
+        let msg = msg::TransformStamped {
+            child_frame_id: source_frame.to_string(),
+            header: msg::Header {
+                frame_id: target_frame.to_string(),
+                stamp: time,
+                seq: 1
+            },
+            transform: isometry.into()
+        };
+        Ok((msg, covariance))
+    }
+
+    /// Looks up the transforms from `source_frame` to each of `target_frames` at the same time.
+    ///
+    /// This is equivalent to calling `lookup_transform` for every target, but the paths are
+    /// walked from the source once so that edges shared between targets are only interpolated
+    /// once. The results are returned in the order of `target_frames`.
+    pub fn lookup_transforms(&self, target_frames: &[&str], source_frame: &str, time: Time) -> Vec<Result<msg::TransformStamped, TfError>> {
+        let source_frame = match validate_frame_id(source_frame) {
+            Ok(source_frame) => source_frame,
+            Err(err) => return vec![Err(err); target_frames.len()]
+        };
+        let mut from_source = HashMap::new();
+        target_frames
+            .iter()
+            .map(|target_frame| {
+                let target_frame = validate_frame_id(target_frame)?;
+                let isometry = self.chain_path_memoized(source_frame, target_frame, time, &mut from_source)?;
+                Ok(msg::TransformStamped {
+                    child_frame_id: source_frame.to_string(),
+                    header: msg::Header {
+                        frame_id: target_frame.to_string(),
+                        stamp: time,
+                        seq: 1
+                    },
+                    transform: isometry.inverse().into()
+                })
+            })
+            .collect()
+    }
+
+    /// Chains the transforms along the path between two validated frame ids, reusing and filling
+    /// `from_source` which holds the transforms from `source_frame` to the frames already visited.
+    fn chain_path_memoized(&self, source_frame: &str, target_frame: &str, time: Time,
+        from_source: &mut HashMap<FrameId, Result<geometry::Isometry3<f64>, TfError>>) -> Result<geometry::Isometry3<f64>, TfError> {
+        if source_frame == target_frame {
+            return Ok(geometry::Isometry3::identity());
+        }
+        let source = self.frames.get(source_frame).ok_or(TfError::CouldNotFindTransform)?;
+        let target = self.frames.get(target_frame).ok_or(TfError::CouldNotFindTransform)?;
+        let path = self.retrieve_transform_path(source, target)?;
+
+        let mut isometry = geometry::Isometry3::identity();
+        let mut parent = source;
+        for child in path.iter() {
+            isometry = from_source
+                .entry(*child)
+                .or_insert_with(|| {
+                    Ok(isometry * self.edge_isometry(&TfGraphNode{child: *child, parent}, time)?)
+                })
+                .clone()?;
+            parent = *child;
+        }
+        Ok(isometry)
+    }
+
+    /// Chains the transforms along the path between two validated frame ids.
+    fn chain_path(&self, source_frame: &str, target_frame: &str, time: Time) -> Result<geometry::Isometry3<f64>, TfError> {
+        if source_frame == target_frame {
+            return Ok(geometry::Isometry3::identity());
+        }
+        let source = self.frames.get(source_frame).ok_or(TfError::CouldNotFindTransform)?;
+        let target = self.frames.get(target_frame).ok_or(TfError::CouldNotFindTransform)?;
+        let path = self.retrieve_transform_path(source, target)?;
+
+        let mut isometry = geometry::Isometry3::identity();
+        let mut parent = source;
+        for child in path.iter() {
+            isometry *= self.edge_isometry(&TfGraphNode{child: *child, parent}, time)?;
+            parent = *child;
+        }
+        Ok(isometry)
+    }
 }
 
 impl TransformInterface for TfBuffer {
     
     /// Looks up a transform within the tree at a given time.
-    fn lookup_transform(&self, source_frame: &str, target_frame: &str, time: rosrust::Time) -> Result<msg::TransformStamped,TfError> {
-        let source_frame = source_frame.to_string();
-        let target_frame = target_frame.to_string();
-        let path = self.retrieve_transform_path(source_frame.clone(), target_frame.clone())?;
-        
-        let mut tflist = Vec::<msg::Transform>::new();
-        let mut first = source_frame.clone();
-        for intermediate in path {
-            let node = TfGraphNode{child: intermediate.clone(), parent: first.clone()};
-            let time_cache = self.transform_data.get(&node).unwrap();
-            let transform = time_cache.get_closest_transform(time)?.transform.clone();
-            let tf = msg::Transform{
-                rotation: msg::Quaternion{
-                    x: transform.rotation.x, 
-                    y: transform.rotation.y, 
-                    z: transform.rotation.z,
-                    w: transform.rotation.w
-                },
-                translation: msg::Vector3 {
-                    x: transform.translation.x, 
-                    y: transform.translation.y, 
-                    z: transform.translation.z
-                }
-            } ;
-            tflist.push(tf);
-            first = intermediate.clone();                  
-        }
-        let final_tf = transforms::chain_transforms(tflist);
+    fn lookup_transform(&self, target_frame: &str, source_frame: &str, time: Time) -> Result<msg::TransformStamped,TfError> {
+        let target_frame = validate_frame_id(target_frame)?;
+        let source_frame = validate_frame_id(source_frame)?;
+        let isometry = self.chain_path(target_frame, source_frame, time)?;
         let msg = msg::TransformStamped {
-            child_frame_id: target_frame.clone(),
+            child_frame_id: source_frame.to_string(),
             header: msg::Header {
-                frame_id: source_frame.clone(), 
+                frame_id: target_frame.to_string(), 
                 stamp: time,
                 seq: 1
             },
-            transform: msg::Transform{
-                rotation: msg::Quaternion{
-                    x: final_tf.rotation.x, y: final_tf.rotation.y, z: final_tf.rotation.z, w: final_tf.rotation.w
-                },
-                translation: msg::Vector3{
-                    x: final_tf.translation.x, y: final_tf.translation.y, z: final_tf.rotation.z
-                }
-            }
+            transform: isometry.into()
         };
         Ok(msg)
     }
 
-    // TODO(MathuxNY-73) implement those methods
-    fn can_transform(&self, _target_frame: &str, _source_frame: &str, _time: rosrust::Time, _timeout: rosrust::Duration) -> Result<bool, TfError> {todo!()}
-
-    fn transform_to_output<'a, T>(&self, _input: &'a T, _output: &'a T, _target_frame: &str, _timeout: Option<rosrust::Duration>) -> &'a T {todo!()}
-    fn transform_from_input<T>(&self, _input: T, _target: &str, _timeout: Option<rosrust::Duration>) -> T {todo!()}
+    /// Checks whether a transform can be looked up. The buffer cannot change while it is
+    /// borrowed so the timeout is ignored, `TfListener` waits for transforms to arrive.
+    fn can_transform(&self, target_frame: &str, source_frame: &str, time: Time, _timeout: Duration) -> Result<bool, TfError> {
+        match self.lookup_transform(target_frame, source_frame, time) {
+            Ok(_) => Ok(true),
+            Err(TfError::InvalidArgument(reason)) => Err(TfError::InvalidArgument(reason)),
+            Err(_) => Ok(false)
+        }
+    }
 }
 
 impl TransformWithTimeInterface for TfBuffer {
-    fn lookup_transform_with_time_travel(&self, target_frame: &str, target_time: rosrust::Time, source_frame: &str, source_time: rosrust::Time,  fixed_frame: &str, _timeout: rosrust::Duration) ->  Result<msg::TransformStamped,TfError> {
-        let source_tf = self.lookup_transform(source_frame, fixed_frame, source_time)?;
-        let target_tf = self.lookup_transform(target_frame, fixed_frame, target_time)?;
+    /// Looks up the transform from `source_frame` at `source_time` to `target_frame` at
+    /// `target_time`, assuming `fixed_frame` does not move in between, like tf2's six argument
+    /// `lookupTransform`. The header is `target_frame` at `target_time` and the child
+    /// `source_frame`.
+    ///
+    /// Like `can_transform`, the timeout is ignored, `TfListener` waits for transforms to arrive.
+    fn lookup_transform_with_time_travel(&self, target_frame: &str, target_time: Time, source_frame: &str, source_time: Time, fixed_frame: &str, _timeout: Duration) -> Result<msg::TransformStamped,TfError> {
+        let target_frame = validate_frame_id(target_frame)?;
+        let source_frame = validate_frame_id(source_frame)?;
+        let fixed_frame = validate_frame_id(fixed_frame)?;
+        let fixed_to_source = self.chain_path(fixed_frame, source_frame, source_time)?;
+        let target_to_fixed = self.chain_path(target_frame, fixed_frame, target_time)?;
 
-        let transforms = transforms::invert_transform(source_tf.transform); // TODO same here it seems that source_tf could be moved.
+        let result = target_to_fixed * fixed_to_source;
 
-        let result = transforms::chain_transforms(vec!(target_tf.transform, transforms));
+        Ok(to_transform_stamped(result.into(), target_frame.to_string(), source_frame.to_string(), target_time))
+    }
 
-        Ok(to_transform_stamped(result, source_frame.to_string(), target_frame.to_string(), source_time))
+    fn can_transform_with_time_travel(&self, target_frame: &str, target_time: Time, source_frame: &str, source_time: Time, fixed_frame: &str,
+        timeout: Duration) -> Result<bool, TfError> {
+        match self.lookup_transform_with_time_travel(target_frame, target_time, source_frame, source_time, fixed_frame, timeout) {
+            Ok(_) => Ok(true),
+            Err(TfError::InvalidArgument(reason)) => Err(TfError::InvalidArgument(reason)),
+            Err(_) => Ok(false)
+        }
     }
+}
 
-    fn can_transform_with_time_travel(&self, _target_frame: &str, _target_time: rosrust::Time, _source_frame: &str, _source_time: rosrust::Time, _fixed_frame: &str, 
-        _timeout: rosrust::Duration) -> Result<bool, TfError> {todo!()}
+/// Makes a lookup in a buffer filled by another thread, retrying up to `timeout` for the
+/// transforms it needs to arrive. The wait goes through the clock of the buffer.
+pub(crate) fn wait_for_lookup<T, F>(buffer: &RwLock<TfBuffer>, timeout: Duration, lookup: F) -> Result<T, TfError>
+where F: Fn(&TfBuffer) -> Result<T, TfError> {
+    let clock = buffer.read().map_err(|_| TfError::CouldNotAcquireLock)?.clock().clone();
+    poll_until(&*clock, timeout, || {
+        let buffer = buffer.read().map_err(|_| TfError::CouldNotAcquireLock)?;
+        match lookup(&buffer) {
+            // Malformed frame ids will not get any better
+            Err(TfError::InvalidArgument(reason)) => Ok(Err(TfError::InvalidArgument(reason))),
+            Err(err) => Err(err),
+            Ok(transform) => Ok(Ok(transform))
+        }
+    })?
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::clock::ManualClock;
     /// This function builds a tree consisting of the following items:
     /// * a world coordinate frame
     /// * an item in the world frame at (1,0,0)
@@ -204,7 +778,7 @@ mod test {
             child_frame_id: "item".to_string(),
             header: msg::Header {
                 frame_id: "world".to_string(),
-                stamp: rosrust::Time{sec: time.floor() as u32, nsec: nsecs},
+                stamp: Time{sec: time.floor() as u32, nsec: nsecs},
                 seq: 1
             },
             transform: msg::Transform{
@@ -217,14 +791,14 @@ mod test {
            }
         };
         let world_to_item_inverse = get_inverse(world_to_item.clone());
-        buffer.add_transform(world_to_item, true);
-        buffer.add_transform(world_to_item_inverse, true);
+        buffer.add_transform(world_to_item, true, "test");
+        buffer.add_transform(world_to_item_inverse, true, "test");
 
         let world_to_base_link = msg::TransformStamped {
             child_frame_id: "base_link".to_string(),
             header: msg::Header {
                 frame_id: "world".to_string(),
-                stamp: rosrust::Time{sec: time.floor() as u32, nsec: nsecs},
+                stamp: Time{sec: time.floor() as u32, nsec: nsecs},
                 seq: 1
             },
             transform: msg::Transform{
@@ -237,14 +811,14 @@ mod test {
            }
         };
         let world_to_base_link_inv = get_inverse(world_to_base_link.clone());
-        buffer.add_transform(world_to_base_link, false);
-        buffer.add_transform(world_to_base_link_inv,  false);
+        buffer.add_transform(world_to_base_link, false, "test");
+        buffer.add_transform(world_to_base_link_inv, false, "test");
 
         let base_link_to_camera = msg::TransformStamped {
             child_frame_id: "camera".to_string(),
             header: msg::Header {
                 frame_id: "base_link".to_string(),
-                stamp: rosrust::Time{sec: time.floor() as u32, nsec: nsecs},
+                stamp: Time{sec: time.floor() as u32, nsec: nsecs},
                 seq: 1
             },
             transform: msg::Transform{
@@ -257,8 +831,8 @@ mod test {
            }
         };
         let base_link_to_camera_inv = get_inverse(base_link_to_camera.clone());
-        buffer.add_transform(base_link_to_camera, true);
-        buffer.add_transform(base_link_to_camera_inv, true);
+        buffer.add_transform(base_link_to_camera, true, "test");
+        buffer.add_transform(base_link_to_camera_inv, true, "test");
     }
 
 
@@ -267,12 +841,12 @@ mod test {
     fn test_basic_tf_lookup() {
         let mut tf_buffer = TfBuffer::new();
         build_test_tree(&mut tf_buffer, 0f64);
-        let res = tf_buffer.lookup_transform("camera", "item", rosrust::Time{sec:0, nsec:0});
+        let res = tf_buffer.lookup_transform("camera", "item", Time{sec:0, nsec:0});
         let expected = msg::TransformStamped {
             child_frame_id: "item".to_string(),
             header: msg::Header {
                 frame_id: "camera".to_string(), 
-                stamp: rosrust::Time{sec:0, nsec:0},
+                stamp: Time{sec:0, nsec:0},
                 seq: 1
             },
             transform: msg::Transform{
@@ -287,18 +861,55 @@ mod test {
         assert_eq!(res.unwrap(), expected);
     }
 
+    /// Tests that the isometry lookup agrees with the message lookup
+    #[test]
+    fn test_basic_isometry_lookup() {
+        let mut tf_buffer = TfBuffer::new();
+        build_test_tree(&mut tf_buffer, 0f64);
+        build_test_tree(&mut tf_buffer, 1f64);
+        let time = Time{sec:0, nsec:700_000_000};
+        let isometry = tf_buffer.lookup_isometry("camera", "item", time).unwrap();
+        assert!((isometry.translation.vector - nalgebra::Vector3::new(0.5f64, -0.7f64, 0f64)).norm() < 1e-9);
+        let msg = tf_buffer.lookup_transform("camera", "item", time).unwrap();
+        assert_eq!(msg.transform, isometry.into());
+    }
+
+    /// Tests that the z translation of a lookup comes from the translation of the chained
+    /// transforms, and not from the z component of their rotation
+    #[test]
+    fn test_z_translation_lookup() {
+        let mut tf_buffer = TfBuffer::new();
+        let world_to_drone = msg::TransformStamped {
+            child_frame_id: "drone".to_string(),
+            header: msg::Header {
+                frame_id: "world".to_string(),
+                stamp: Time{sec: 0, nsec: 0},
+                seq: 1
+            },
+            transform: msg::Transform {
+                translation: msg::Vector3{x: 1f64, y: 2f64, z: 3f64},
+                rotation: msg::Quaternion{x: 0f64, y: 0f64, z: 0.5f64.sqrt(), w: 0.5f64.sqrt()}
+            }
+        };
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(world_to_drone)}, true, "test").unwrap();
+        let res = tf_buffer.lookup_transform("world", "drone", Time{sec: 0, nsec: 0}).unwrap();
+        assert!((res.transform.translation.z - 3f64).abs() < 1e-9);
+        assert!((res.transform.translation.x - 1f64).abs() < 1e-9);
+        assert!((res.transform.translation.y - 2f64).abs() < 1e-9);
+    }
+
     /// Tests an interpolated lookup. 
     #[test]
     fn test_basic_tf_interpolation() {
         let mut tf_buffer = TfBuffer::new();
         build_test_tree(&mut tf_buffer, 0f64);
         build_test_tree(&mut tf_buffer, 1f64);
-        let res = tf_buffer.lookup_transform("camera", "item", rosrust::Time{sec:0, nsec:700_000_000});
+        let res = tf_buffer.lookup_transform("camera", "item", Time{sec:0, nsec:700_000_000});
         let expected = msg::TransformStamped {
             child_frame_id: "item".to_string(),
             header: msg::Header {
                 frame_id: "camera".to_string(), 
-                stamp: rosrust::Time{sec:0, nsec:700_000_000},
+                stamp: Time{sec:0, nsec:700_000_000},
                 seq: 1
             },
             transform: msg::Transform{
@@ -319,12 +930,12 @@ mod test {
         let mut tf_buffer = TfBuffer::new();
         build_test_tree(&mut tf_buffer, 0f64);
         build_test_tree(&mut tf_buffer, 1f64);
-        let res = tf_buffer.lookup_transform_with_time_travel("camera", rosrust::Time{sec:0, nsec: 400_000_000}, "camera", rosrust::Time{sec:0, nsec: 700_000_000}, "item", rosrust::Duration{sec:0, nsec: 700_000_000});
+        let res = tf_buffer.lookup_transform_with_time_travel("camera", Time{sec:0, nsec: 400_000_000}, "camera", Time{sec:0, nsec: 700_000_000}, "item", Duration{sec:0, nsec: 700_000_000});
         let expected = msg::TransformStamped {
             child_frame_id: "camera".to_string(),
             header: msg::Header {
                 frame_id: "camera".to_string(), 
-                stamp: rosrust::Time{sec:0, nsec:700_000_000},
+                stamp: Time{sec:0, nsec:400_000_000},
                 seq: 0
             },
             transform: msg::Transform{
@@ -339,6 +950,495 @@ mod test {
         assert_approx_eq(res.unwrap(), expected);
     }
 
+    /// Tests time travel between different frames against tf2: the result maps the source frame
+    /// at the source time to the target frame at the target time through the fixed frame
+    #[test]
+    fn test_timetravel_between_frames() {
+        let mut tf_buffer = TfBuffer::new();
+        build_test_tree(&mut tf_buffer, 0f64);
+        build_test_tree(&mut tf_buffer, 1f64);
+        let target_time = Time{sec:0, nsec: 200_000_000};
+        let source_time = Time{sec:0, nsec: 900_000_000};
+        let res = tf_buffer.lookup_transform_with_time_travel("base_link", target_time, "camera", source_time, "world", Duration::default()).unwrap();
+        assert_eq!(res.header.frame_id, "base_link");
+        assert_eq!(res.child_frame_id, "camera");
+        assert_eq!(res.header.stamp, target_time);
+        let expected = tf_buffer.lookup_isometry("base_link", "world", target_time).unwrap() * tf_buffer.lookup_isometry("world", "camera", source_time).unwrap();
+        assert_eq!(res.transform, expected.into());
+        assert!((expected.translation.vector - nalgebra::Vector3::new(0.5f64, 0.7f64, 0f64)).norm() < 1e-9);
+
+        // With the same time on both legs, it is a plain lookup
+        let plain = tf_buffer.lookup_transform_with_time_travel("camera", source_time, "item", source_time, "world", Duration::default()).unwrap();
+        let mut expected = tf_buffer.lookup_transform("camera", "item", source_time).unwrap();
+        expected.header.seq = 0;
+        assert_approx_eq(plain, expected);
+
+        assert!(tf_buffer.can_transform_with_time_travel("item", target_time, "camera", source_time, "world", Duration::default()).unwrap());
+        assert!(!tf_buffer.can_transform_with_time_travel("item", target_time, "unknown", source_time, "world", Duration::default()).unwrap());
+        assert!(tf_buffer.can_transform_with_time_travel("item", target_time, "camera", source_time, "", Duration::default()).is_err());
+    }
+
+    fn make_transform(parent: &str, child: &str, x: f64) -> msg::TransformStamped {
+        msg::TransformStamped {
+            child_frame_id: child.to_string(),
+            header: msg::Header {
+                frame_id: parent.to_string(),
+                stamp: Time{sec: 0, nsec: 0},
+                seq: 1
+            },
+            transform: msg::Transform{
+                rotation: msg::Quaternion{
+                    x: 0f64, y: 0f64, z: 0f64, w: 1f64
+                },
+                translation: msg::Vector3{
+                    x, y: 0f64, z: 0f64
+                }
+            }
+        }
+    }
+
+    /// Tests that a transform closing a loop is refused while the rest of the message is kept
+    #[test]
+    fn test_loop_is_rejected() {
+        let mut tf_buffer = TfBuffer::new();
+        let message = msg::TFMessage{transforms: vec!(
+            make_transform("world", "base_link", 1f64),
+            make_transform("base_link", "camera", 1f64),
+            make_transform("camera", "world", 1f64),
+            make_transform("world", "item", 3f64)
+        )};
+        let res = tf_buffer.handle_incoming_transforms(message, true, "test");
+        assert!(matches!(res, Err(TfError::LoopDetected(_))));
+
+        let time = Time{sec: 0, nsec: 0};
+        let tf = tf_buffer.lookup_transform("camera", "world", time).unwrap();
+        assert_eq!(tf.transform.translation.x, -2f64);
+        let tf = tf_buffer.lookup_transform("world", "item", time).unwrap();
+        assert_eq!(tf.transform.translation.x, 3f64);
+    }
+
+    /// Tests that a frame published with a new parent is moved under that parent
+    #[test]
+    fn test_frame_is_reparented() {
+        let mut tf_buffer = TfBuffer::new();
+        let message = msg::TFMessage{transforms: vec!(
+            make_transform("world", "base_link", 1f64),
+            make_transform("base_link", "camera", 1f64),
+            make_transform("world", "camera", 5f64)
+        )};
+        tf_buffer.handle_incoming_transforms(message, true, "test").unwrap();
+
+        let time = Time{sec: 0, nsec: 0};
+        let tf = tf_buffer.lookup_transform("world", "camera", time).unwrap();
+        assert_eq!(tf.transform.translation.x, 5f64);
+        let tf = tf_buffer.lookup_transform("base_link", "camera", time).unwrap();
+        assert_eq!(tf.transform.translation.x, 4f64);
+    }
+
+    /// Tests that frame ids are normalized on insertion and lookup, and malformed ones refused
+    #[test]
+    fn test_frame_id_validation() {
+        let mut tf_buffer = TfBuffer::new();
+        let message = msg::TFMessage{transforms: vec!(
+            make_transform("/world", "base_link", 1f64),
+            make_transform("world", "", 1f64),
+            make_transform("base_link", "base_link", 1f64),
+            make_transform("base link", "camera", 1f64)
+        )};
+        let res = tf_buffer.handle_incoming_transforms(message, true, "test");
+        assert!(matches!(res, Err(TfError::InvalidArgument(_))));
+
+        let time = Time{sec: 0, nsec: 0};
+        let tf = tf_buffer.lookup_transform("/world", "base_link", time).unwrap();
+        assert_eq!(tf.header.frame_id, "world");
+        assert_eq!(tf.transform.translation.x, 1f64);
+        assert!(tf_buffer.lookup_transform("world", "camera", time).is_err());
+        assert!(matches!(tf_buffer.lookup_transform("", "base_link", time), Err(TfError::InvalidArgument(_))));
+    }
+
+    /// Tests that the shortest path is found and that the cache follows topology changes
+    #[test]
+    fn test_shortest_path() {
+        let mut tf_buffer = TfBuffer::new();
+        let message = msg::TFMessage{transforms: vec!(
+            make_transform("world", "a", 1f64),
+            make_transform("a", "b", 1f64),
+            make_transform("b", "c", 1f64),
+            make_transform("world", "d", 1f64)
+        )};
+        tf_buffer.handle_incoming_transforms(message, true, "test").unwrap();
+        let path = |tf_buffer: &TfBuffer, from, to| {
+            let from = tf_buffer.frames.get(from).unwrap();
+            let to = tf_buffer.frames.get(to).unwrap();
+            tf_buffer.retrieve_transform_path(from, to).unwrap()
+                .iter()
+                .map(|frame| tf_buffer.frames.name(*frame).to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(path(&tf_buffer, "d", "c"), vec!("world", "a", "b", "c"));
+
+        let message = msg::TFMessage{transforms: vec!(make_transform("d", "c", 1f64))};
+        tf_buffer.handle_incoming_transforms(message, true, "test").unwrap();
+        assert_eq!(path(&tf_buffer, "d", "c"), vec!("c"));
+        assert_eq!(path(&tf_buffer, "a", "c"), vec!("world", "d", "c"));
+    }
+
+    /// Tests that paths deeper than the maximum graph depth are refused
+    #[test]
+    fn test_max_depth() {
+        let mut tf_buffer = TfBuffer::new();
+        let transforms = (0..MAX_GRAPH_DEPTH + 1)
+            .map(|i| make_transform(&format!("frame_{}", i), &format!("frame_{}", i + 1), 1f64))
+            .collect();
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms}, true, "test").unwrap();
+
+        let time = Time{sec: 0, nsec: 0};
+        let last = format!("frame_{}", MAX_GRAPH_DEPTH);
+        let tf = tf_buffer.lookup_transform("frame_0", &last, time).unwrap();
+        assert_eq!(tf.transform.translation.x, MAX_GRAPH_DEPTH as f64);
+        let too_far = format!("frame_{}", MAX_GRAPH_DEPTH + 1);
+        assert!(matches!(tf_buffer.lookup_transform("frame_0", &too_far, time), Err(TfError::MaxDepthExceeded)));
+        assert!(matches!(tf_buffer.lookup_transform("frame_0", "unknown", time), Err(TfError::CouldNotFindTransform)));
+    }
+
+    /// Tests that batch lookups agree with individual lookups
+    #[test]
+    fn test_batch_lookup() {
+        let mut tf_buffer = TfBuffer::new();
+        build_test_tree(&mut tf_buffer, 0f64);
+        build_test_tree(&mut tf_buffer, 1f64);
+        let time = Time{sec:0, nsec:700_000_000};
+        let targets = ["item", "world", "camera", "unknown", "base_link"];
+        let results = tf_buffer.lookup_transforms(&targets, "camera", time);
+        assert_eq!(results.len(), targets.len());
+        for (target, result) in targets.iter().zip(results) {
+            match tf_buffer.lookup_transform(target, "camera", time) {
+                Ok(expected) => assert_approx_eq(result.unwrap(), expected),
+                Err(_) => assert!(result.is_err())
+            }
+        }
+    }
+
+    /// Tests that edge covariances are moved along the path and through inverted edges
+    #[test]
+    fn test_covariance_propagation() {
+        let mut tf_buffer = TfBuffer::new();
+        let message = msg::TFMessage{transforms: vec!(
+            make_transform("world", "a", 1f64),
+            make_transform("a", "b", 1f64)
+        )};
+        tf_buffer.handle_incoming_transforms(message, true, "test").unwrap();
+        let mut yaw_covariance = Matrix6::zeros();
+        yaw_covariance[(5, 5)] = 0.01f64;
+        tf_buffer.set_transform_covariance("a", "b", yaw_covariance).unwrap();
+        assert!(tf_buffer.set_transform_covariance("b", "a", yaw_covariance).is_err());
+
+        let time = Time{sec: 0, nsec: 0};
+        let (tf, covariance) = tf_buffer.lookup_transform_with_covariance("world", "b", time).unwrap();
+        assert_eq!(tf.transform.translation.x, 2f64);
+        let mut expected = yaw_covariance;
+        expected[(1, 1)] = 0.01f64;
+        expected[(1, 5)] = -0.01f64;
+        expected[(5, 1)] = -0.01f64;
+        assert!((covariance - expected).norm() < 1e-12);
+
+        let (_, covariance) = tf_buffer.lookup_transform_with_covariance("b", "world", time).unwrap();
+        expected[(1, 5)] = 0.01f64;
+        expected[(5, 1)] = 0.01f64;
+        assert!((covariance - expected).norm() < 1e-12);
+
+        let (_, covariance) = tf_buffer.lookup_transform_with_covariance("world", "a", time).unwrap();
+        assert_eq!(covariance, Matrix6::zeros());
+    }
+
+    /// Tests that transforms with bad values are refused and counted, and others normalized
+    #[test]
+    fn test_transform_value_validation() {
+        let mut tf_buffer = TfBuffer::new();
+        let mut zero_quaternion = make_transform("world", "base_link", 1f64);
+        zero_quaternion.transform.rotation.w = 0f64;
+        let mut nan_translation = make_transform("world", "base_link", f64::NAN);
+        nan_translation.header.stamp.sec = 1;
+        let mut infinite_quaternion = make_transform("world", "camera", 1f64);
+        infinite_quaternion.transform.rotation.x = f64::INFINITY;
+        let mut scaled_quaternion = make_transform("world", "item", 1f64);
+        scaled_quaternion.transform.rotation = msg::Quaternion{x: 0f64, y: 0f64, z: 2f64, w: 2f64};
+        let message = msg::TFMessage{transforms: vec!(zero_quaternion, nan_translation, infinite_quaternion, scaled_quaternion)};
+        let res = tf_buffer.handle_incoming_transforms(message, false, "test");
+        assert!(matches!(res, Err(TfError::InvalidArgument(_))));
+
+        assert_eq!(tf_buffer.rejected_count("world", "base_link"), 2);
+        assert_eq!(tf_buffer.rejected_count("world", "camera"), 1);
+        assert_eq!(tf_buffer.rejected_count("world", "item"), 0);
+        let time = Time{sec: 0, nsec: 0};
+        assert!(tf_buffer.lookup_transform("world", "base_link", time).is_err());
+        let tf = tf_buffer.lookup_transform("world", "item", time).unwrap();
+        assert!((tf.transform.rotation.z - 0.5f64.sqrt()).abs() < 1e-12);
+        assert!((tf.transform.rotation.w - 0.5f64.sqrt()).abs() < 1e-12);
+    }
+
+    /// Tests that a transform refused for its values interns no frame and raises no authority
+    /// conflict
+    #[test]
+    fn test_rejected_transform_leaves_no_state() {
+        let mut tf_buffer = TfBuffer::new();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded_events = events.clone();
+        tf_buffer.subscribe(move |event| recorded_events.lock().unwrap().push(format!("{:?}", event)));
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(make_transform("world", "base_link", 1f64))}, false, "/a").unwrap();
+        events.lock().unwrap().clear();
+
+        let mut conflicting = make_transform("world", "base_link", f64::NAN);
+        conflicting.header.stamp.sec = 1;
+        let mut unknown = make_transform("world", "camera", 1f64);
+        unknown.transform.rotation.w = 0f64;
+        let res = tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(conflicting, unknown)}, false, "/b");
+        assert!(matches!(res, Err(TfError::InvalidArgument(_))));
+
+        assert!(tf_buffer.frames.get("camera").is_none());
+        assert_eq!(tf_buffer.frame_authority("base_link"), Some("/a"));
+        assert_eq!(tf_buffer.rejected_count("world", "camera"), 1);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|event| event.starts_with("MessageRejected")));
+    }
+
+    /// Tests that a static transform holds the value it was last updated with at any time
+    #[test]
+    fn test_static_transform_update() {
+        let mut tf_buffer = TfBuffer::new();
+        let mut first = make_transform("base_link", "camera", 1f64);
+        first.header.stamp.sec = 10;
+        let second = make_transform("base_link", "camera", 2f64);
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(first.clone())}, true, "test").unwrap();
+        let static_usage = tf_buffer.static_memory_usage();
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(second)}, true, "test").unwrap();
+        assert_eq!(tf_buffer.static_memory_usage(), static_usage);
+        assert_eq!(tf_buffer.dynamic_memory_usage(), 0);
+
+        for sec in [0, 5, 100] {
+            let tf = tf_buffer.lookup_transform("base_link", "camera", Time{sec, nsec: 0}).unwrap();
+            assert_eq!(tf.transform.translation.x, 2f64);
+        }
+
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(first)}, false, "test").unwrap();
+        assert_eq!(tf_buffer.static_memory_usage(), 0);
+        assert!(tf_buffer.dynamic_memory_usage() > 0);
+        let tf = tf_buffer.lookup_transform("base_link", "camera", Time{sec: 10, nsec: 0}).unwrap();
+        assert_eq!(tf.transform.translation.x, 1f64);
+    }
+
+    /// Tests that removing a frame drops its edges and disconnects the tree
+    #[test]
+    fn test_remove_frame() {
+        let mut tf_buffer = TfBuffer::new();
+        build_test_tree(&mut tf_buffer, 0f64);
+        let time = Time{sec: 0, nsec: 0};
+        assert!(tf_buffer.lookup_transform("camera", "item", time).is_ok());
+
+        assert!(tf_buffer.remove_frame("base_link"));
+        assert!(!tf_buffer.remove_frame("base_link"));
+        assert_eq!(tf_buffer.frames(), vec!("item", "world"));
+        assert!(matches!(tf_buffer.lookup_transform("camera", "item", time), Err(TfError::CouldNotFindTransform)));
+        assert!(tf_buffer.lookup_transform("world", "item", time).is_ok());
+
+        tf_buffer.clear();
+        assert!(tf_buffer.frames().is_empty());
+        assert_eq!(tf_buffer.static_memory_usage(), 0);
+    }
+
+    /// Tests that a removed frame leaves no state behind and can come back under another parent
+    #[test]
+    fn test_remove_frame_and_add_it_back() {
+        let mut tf_buffer = TfBuffer::new();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded_events = events.clone();
+        tf_buffer.subscribe(move |event| recorded_events.lock().unwrap().push(format!("{:?}", event)));
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(
+            make_transform("world", "base_link", 1f64),
+            make_transform("base_link", "camera", 2f64),
+            make_transform("world", "map", 4f64)
+        )}, true, "test").unwrap();
+        let mut invalid = make_transform("camera", "world", 1f64);
+        invalid.transform.rotation.w = 0f64;
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(invalid)}, true, "test").unwrap_err();
+        let time = Time{sec: 0, nsec: 0};
+        assert!(tf_buffer.lookup_transform("map", "camera", time).is_ok());
+        events.lock().unwrap().clear();
+
+        assert!(tf_buffer.remove_frame("base_link"));
+        assert_eq!(*events.lock().unwrap(), vec!(
+            "FrameRemoved { frame_id: \"base_link\" }",
+            "FrameRemoved { frame_id: \"camera\" }"
+        ));
+        assert_eq!(tf_buffer.rejected_count("camera", "world"), 0);
+        assert!(tf_buffer.frames.get("base_link").is_none());
+        assert!(tf_buffer.frames.get("camera").is_none());
+
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(
+            make_transform("map", "base_link", 8f64),
+            make_transform("base_link", "camera", 16f64)
+        )}, true, "test").unwrap();
+        let tf = tf_buffer.lookup_transform("world", "camera", time).unwrap();
+        assert_eq!(tf.transform.translation.x, 28f64);
+        let tf = tf_buffer.lookup_transform("map", "camera", time).unwrap();
+        assert_eq!(tf.transform.translation.x, 24f64);
+
+        events.lock().unwrap().clear();
+        tf_buffer.clear();
+        assert_eq!(events.lock().unwrap().len(), 4);
+        assert!(tf_buffer.frames.get("world").is_none());
+    }
+
+    /// Tests that a jump back in time clears the dynamic transforms only
+    #[test]
+    fn test_time_jump_detection() {
+        let mut tf_buffer = TfBuffer::new();
+        build_test_tree(&mut tf_buffer, 5f64);
+        build_test_tree(&mut tf_buffer, 6f64);
+        assert!(!tf_buffer.update_time(Time{sec: 6, nsec: 0}));
+        assert!(!tf_buffer.update_time(Time{sec: 1, nsec: 0}));
+
+        tf_buffer.set_time_jump_detection(true);
+        assert!(!tf_buffer.update_time(Time{sec: 6, nsec: 0}));
+        assert!(tf_buffer.update_time(Time{sec: 1, nsec: 0}));
+        assert!(tf_buffer.lookup_transform("world", "base_link", Time{sec: 5, nsec: 0}).is_err());
+
+        build_test_tree(&mut tf_buffer, 1f64);
+        let time = Time{sec: 1, nsec: 0};
+        let tf = tf_buffer.lookup_transform("world", "base_link", time).unwrap();
+        assert_eq!(tf.transform.translation.y, 1f64);
+        assert!(tf_buffer.lookup_transform("base_link", "camera", time).is_ok());
+    }
+
+    /// Tests that frames left without transforms by a time jump are reported as removed, and
+    /// added again when they are republished
+    #[test]
+    fn test_clear_dynamic_events() {
+        let mut tf_buffer = TfBuffer::new();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded_events = events.clone();
+        tf_buffer.subscribe(move |event| recorded_events.lock().unwrap().push(format!("{:?}", event)));
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(make_transform("world", "odom", 1f64))}, true, "test").unwrap();
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(make_transform("odom", "base", 1f64))}, false, "test").unwrap();
+        let mut invalid = make_transform("base", "camera", 1f64);
+        invalid.transform.rotation.w = 0f64;
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(invalid)}, false, "test").unwrap_err();
+        events.lock().unwrap().clear();
+
+        tf_buffer.clear_dynamic();
+        assert_eq!(tf_buffer.frames(), vec!("odom", "world"));
+        assert_eq!(tf_buffer.rejected_count("base", "camera"), 0);
+        assert!(tf_buffer.frames.get("base").is_none());
+
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(make_transform("odom", "base", 2f64))}, false, "test").unwrap();
+        assert_eq!(*events.lock().unwrap(), vec!(
+            "FrameRemoved { frame_id: \"base\" }",
+            "FrameAdded { frame_id: \"base\" }"
+        ));
+        let tf = tf_buffer.lookup_transform("world", "base", Time{sec: 0, nsec: 0}).unwrap();
+        assert_eq!(tf.transform.translation.x, 3f64);
+    }
+
+    /// Tests that incoming transforms are checked against the clock of the buffer
+    #[test]
+    fn test_time_jump_detection_with_clock() {
+        let clock = ManualClock::new(Time{sec: 5, nsec: 0});
+        let mut tf_buffer = TfBuffer::with_clock(Arc::new(clock.clone()));
+        tf_buffer.set_time_jump_detection(true);
+        let mut transform = make_transform("world", "base_link", 1f64);
+        transform.header.stamp = Time{sec: 5, nsec: 0};
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(transform)}, false, "test").unwrap();
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(make_transform("base_link", "camera", 1f64))}, true, "test").unwrap();
+        assert!(tf_buffer.can_transform("world", "camera", Time{sec: 5, nsec: 0}, Duration::default()).unwrap());
+
+        clock.set(Time{sec: 1, nsec: 0});
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(make_transform("world", "map", 1f64))}, true, "test").unwrap();
+        assert!(!tf_buffer.can_transform("world", "camera", Time{sec: 5, nsec: 0}, Duration::default()).unwrap());
+        assert!(tf_buffer.can_transform("base_link", "camera", Time{sec: 5, nsec: 0}, Duration::default()).unwrap());
+        assert!(tf_buffer.can_transform("", "camera", Time{sec: 5, nsec: 0}, Duration::default()).is_err());
+    }
+
+    /// Tests that the publisher of each frame is recorded and reported
+    #[test]
+    fn test_authority() {
+        let mut tf_buffer = TfBuffer::new();
+        let mut moving = make_transform("world", "base_link", 0f64);
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(moving.clone())}, false, "/odometry").unwrap();
+        moving.header.stamp = Time{sec: 2, nsec: 0};
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(moving)}, false, "/odometry").unwrap();
+        let fixed = make_transform("base_link", "camera", 1f64);
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(fixed.clone())}, true, "/robot_state_publisher").unwrap();
+
+        assert_eq!(tf_buffer.frame_authority("base_link"), Some("/odometry"));
+        assert_eq!(tf_buffer.frame_authority("camera"), Some("/robot_state_publisher"));
+        assert_eq!(tf_buffer.frame_authority("world"), None);
+        assert_eq!(tf_buffer.all_frames_as_yaml(), concat!(
+            "base_link: \n",
+            "  parent: 'world'\n",
+            "  broadcaster: '/odometry'\n",
+            "  rate: 1.000\n",
+            "  most_recent_transform: 2.000\n",
+            "  oldest_transform: 0.000\n",
+            "  buffer_length: 2.000\n",
+            "camera: \n",
+            "  parent: 'base_link'\n",
+            "  broadcaster: '/robot_state_publisher'\n",
+            "  rate: 10000.000\n",
+            "  most_recent_transform: 0.000\n",
+            "  oldest_transform: 0.000\n",
+            "  buffer_length: 0.000\n"));
+
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(fixed)}, true, "/calibration").unwrap();
+        assert_eq!(tf_buffer.frame_authority("camera"), Some("/calibration"));
+    }
+
+    /// Tests that subscribers are notified of the changes of the buffer
+    #[test]
+    fn test_events() {
+        let mut tf_buffer = TfBuffer::new();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded_events = events.clone();
+        let id = tf_buffer.subscribe(move |event| recorded_events.lock().unwrap().push(format!("{:?}", event)));
+
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(
+            make_transform("world", "base_link", 1f64),
+            make_transform("base_link", "camera", 1f64),
+            make_transform("camera", "world", 1f64)
+        )}, true, "/a").unwrap_err();
+        // Refused as a loop, the transform is no conflict with the publisher of base_link
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(
+            make_transform("camera", "base_link", 1f64)
+        )}, true, "/c").unwrap_err();
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(
+            make_transform("base_link", "camera", 2f64),
+            make_transform("world", "camera", 2f64)
+        )}, true, "/b").unwrap();
+        for sec in 0..101 {
+            let mut transform = make_transform("world", "item", 1f64);
+            transform.header.stamp = Time{sec, nsec: 0};
+            tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(transform)}, false, "/b").unwrap();
+        }
+
+        assert_eq!(*events.lock().unwrap(), vec!(
+            "FrameAdded { frame_id: \"world\" }",
+            "FrameAdded { frame_id: \"base_link\" }",
+            "FrameAdded { frame_id: \"camera\" }",
+            "MessageRejected { parent_frame: \"camera\", child_frame: \"world\", error: LoopDetected(\"adding camera as parent of world would create a loop\") }",
+            "MessageRejected { parent_frame: \"camera\", child_frame: \"base_link\", error: LoopDetected(\"adding camera as parent of base_link would create a loop\") }",
+            "AuthorityConflict { frame_id: \"camera\", previous_authority: \"/a\", authority: \"/b\" }",
+            "StaticTransformUpdated { parent_frame: \"base_link\", child_frame: \"camera\" }",
+            "FrameReparented { frame_id: \"camera\", old_parent: \"base_link\", new_parent: \"world\" }",
+            "FrameAdded { frame_id: \"item\" }",
+            "CachePruned { parent_frame: \"world\", child_frame: \"item\", stamp: Time { sec: 0, nsec: 0 } }"
+        ));
+
+        assert!(tf_buffer.unsubscribe(id));
+        assert!(!tf_buffer.unsubscribe(id));
+        tf_buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(make_transform("world", "map", 0f64))}, true, "/b").unwrap();
+        assert_eq!(events.lock().unwrap().len(), 10);
+    }
+
     fn assert_approx_eq(msg1: msg::TransformStamped, msg2: msg::TransformStamped) {
         assert_eq!(msg1.header, msg2.header);
         assert_eq!(msg1.child_frame_id, msg2.child_frame_id);
@@ -0,0 +1,303 @@
+//! Lookups in a remote buffer, like the buffer server of tf2_ros, so that lightweight nodes do
+//! not have to subscribe to `/tf` themselves.
+//!
+//! The server answers `msg::LookupTransformRequest`s with the transforms of a shared buffer,
+//! waiting up to the timeout of the request for them to arrive. Errors travel as
+//! `msg::TF2Error`s, for tf2 tools, along with the code of the `TfError` variant the server met,
+//! which the client turns back into that variant. With the `ros1`
+//! feature both sides talk over a rosrust service, other transports can be plugged in with
+//! `TfBufferServer::unadvertised` and `TfBufferClient::with_call`.
+use std::sync::{
+    Arc,
+    RwLock
+};
+
+use crate::buffer::{
+    wait_for_lookup,
+    TfBuffer
+};
+use crate::core::{
+    TfError,
+    TransformInterface,
+    TransformWithTimeInterface
+};
+use crate::msg;
+use crate::time::{
+    Duration,
+    Time
+};
+
+
+/// The name tf2_ros gives to its buffer server.
+pub const DEFAULT_SERVICE: &str = "tf2_buffer_server";
+
+/// How much longer than the timeout of a request the client waits for the server to answer, as
+/// tf2_ros' client does.
+#[cfg(feature = "ros1")]
+const SERVICE_TIMEOUT_PADDING: Duration = Duration{sec: 2, nsec: 0};
+
+/// Codes of the `TfError` variants in `msg::LookupTransformResponse::tf_error`.
+const NO_TF_ERROR: u8 = 0;
+const ATTEMPTED_LOOKUP_IN_PAST: u8 = 1;
+const ATTEMPTED_LOOKUP_IN_FUTURE: u8 = 2;
+const COULD_NOT_FIND_TRANSFORM: u8 = 3;
+const MAX_DEPTH_EXCEEDED: u8 = 4;
+const COULD_NOT_ACQUIRE_LOCK: u8 = 5;
+const LOOP_DETECTED: u8 = 6;
+const INVALID_ARGUMENT: u8 = 7;
+const TIMEOUT: u8 = 8;
+const COMMUNICATION: u8 = 9;
+
+/// Answers lookups with the transforms of a buffer, usually the one of a `TfListener`.
+pub struct TfBufferServer {
+    buffer: Arc<RwLock<TfBuffer>>,
+    #[cfg(feature = "ros1")]
+    _service: Option<rosrust::Service>
+}
+
+impl TfBufferServer {
+    /// Advertises the rosrust service `service`. ROS has to be initialized.
+    #[cfg(feature = "ros1")]
+    pub fn new(buffer: Arc<RwLock<TfBuffer>>, service: &str) -> rosrust::error::Result<Self> {
+        let service_buffer = buffer.clone();
+        let service = rosrust::service::<msg::LookupTransform, _>(service, move |request| {
+            Ok(handle_request(&service_buffer, request))
+        })?;
+        Ok(TfBufferServer{buffer, _service: Some(service)})
+    }
+
+    /// Creates a server that is not advertised, the requests are handed to it with
+    /// `handle_request` by the transport of the application.
+    pub fn unadvertised(buffer: Arc<RwLock<TfBuffer>>) -> Self {
+        TfBufferServer {
+            buffer,
+            #[cfg(feature = "ros1")]
+            _service: None
+        }
+    }
+
+    pub fn handle_request(&self, request: msg::LookupTransformRequest) -> msg::LookupTransformResponse {
+        handle_request(&self.buffer, request)
+    }
+}
+
+fn handle_request(buffer: &RwLock<TfBuffer>, request: msg::LookupTransformRequest) -> msg::LookupTransformResponse {
+    let res = wait_for_lookup(buffer, request.timeout, |buffer| match request.advanced {
+        true => buffer.lookup_transform_with_time_travel(&request.target_frame, request.target_time,
+            &request.source_frame, request.source_time, &request.fixed_frame, Duration::default()),
+        false => buffer.lookup_transform(&request.target_frame, &request.source_frame, request.source_time)
+    });
+    match res {
+        Ok(transform) => msg::LookupTransformResponse{transform, error: msg::TF2Error::default(), tf_error: NO_TF_ERROR},
+        Err(err) => {
+            let (error, tf_error) = to_tf2_error(&err);
+            msg::LookupTransformResponse {
+                transform: msg::TransformStamped {
+                    header: msg::Header{seq: 0, stamp: Time::default(), frame_id: String::new()},
+                    child_frame_id: String::new(),
+                    transform: msg::Transform::identity()
+                },
+                error,
+                tf_error
+            }
+        }
+    }
+}
+
+/// Returns the tf2 error matching `err` and the code of its variant.
+fn to_tf2_error(err: &TfError) -> (msg::TF2Error, u8) {
+    let (error, error_string, tf_error) = match err {
+        TfError::AttemptedLookupInPast => (msg::TF2Error::EXTRAPOLATION_ERROR, "Lookup would require extrapolation into the past".to_string(), ATTEMPTED_LOOKUP_IN_PAST),
+        TfError::AttemptedLookUpInFuture => (msg::TF2Error::EXTRAPOLATION_ERROR, "Lookup would require extrapolation into the future".to_string(), ATTEMPTED_LOOKUP_IN_FUTURE),
+        TfError::CouldNotFindTransform => (msg::TF2Error::CONNECTIVITY_ERROR, "Could not find a connection between the frames".to_string(), COULD_NOT_FIND_TRANSFORM),
+        TfError::MaxDepthExceeded => (msg::TF2Error::LOOKUP_ERROR, "The path between the frames is longer than the maximum depth of the tree".to_string(), MAX_DEPTH_EXCEEDED),
+        TfError::CouldNotAcquireLock => (msg::TF2Error::TRANSFORM_ERROR, "Could not acquire the lock of the buffer".to_string(), COULD_NOT_ACQUIRE_LOCK),
+        TfError::LoopDetected(reason) => (msg::TF2Error::TRANSFORM_ERROR, reason.clone(), LOOP_DETECTED),
+        TfError::InvalidArgument(reason) => (msg::TF2Error::INVALID_ARGUMENT_ERROR, reason.clone(), INVALID_ARGUMENT),
+        TfError::Timeout => (msg::TF2Error::TIMEOUT_ERROR, "Timed out waiting for the transform".to_string(), TIMEOUT),
+        TfError::Communication(reason) => (msg::TF2Error::TRANSFORM_ERROR, reason.clone(), COMMUNICATION)
+    };
+    (msg::TF2Error{error, error_string}, tf_error)
+}
+
+/// Turns the error of a response back into the `TfError` the server met. Without the code of the
+/// variant, as from a server that does not set it, the closest variant to the tf2 error is used.
+fn from_tf2_error(error: msg::TF2Error, tf_error: u8) -> TfError {
+    let reason = error.error_string;
+    match (tf_error, error.error) {
+        (ATTEMPTED_LOOKUP_IN_PAST, _) => TfError::AttemptedLookupInPast,
+        (ATTEMPTED_LOOKUP_IN_FUTURE, _) => TfError::AttemptedLookUpInFuture,
+        (COULD_NOT_FIND_TRANSFORM, _) => TfError::CouldNotFindTransform,
+        (MAX_DEPTH_EXCEEDED, _) => TfError::MaxDepthExceeded,
+        (COULD_NOT_ACQUIRE_LOCK, _) => TfError::CouldNotAcquireLock,
+        (LOOP_DETECTED, _) => TfError::LoopDetected(reason),
+        (INVALID_ARGUMENT, _) | (_, msg::TF2Error::INVALID_ARGUMENT_ERROR) => TfError::InvalidArgument(reason),
+        (TIMEOUT, _) | (_, msg::TF2Error::TIMEOUT_ERROR) => TfError::Timeout,
+        (COMMUNICATION, _) => TfError::Communication(reason),
+        (_, msg::TF2Error::EXTRAPOLATION_ERROR) => TfError::AttemptedLookupInPast,
+        _ => TfError::CouldNotFindTransform
+    }
+}
+
+type Call = dyn Fn(msg::LookupTransformRequest) -> Result<msg::LookupTransformResponse, TfError> + Send + Sync;
+
+/// Looks up transforms in the buffer of a `TfBufferServer`.
+pub struct TfBufferClient {
+    call: Box<Call>
+}
+
+impl TfBufferClient {
+    /// Calls the rosrust service `service`. ROS has to be initialized.
+    ///
+    /// Requests fail with `TfError::Timeout` when the server does not answer within their
+    /// timeout, plus some padding for the round trip, and with `TfError::Communication` when the
+    /// service cannot be called or refuses them.
+    #[cfg(feature = "ros1")]
+    pub fn new(service: &str) -> rosrust::error::Result<Self> {
+        let client = rosrust::client::<msg::LookupTransform>(service)?;
+        Ok(Self::with_call(move |request| {
+            let wait = request.timeout + SERVICE_TIMEOUT_PADDING;
+            let (sender, receiver) = std::sync::mpsc::channel();
+            client.req_async(request).callback(move |response| {
+                let _ = sender.send(response);
+            });
+            match receiver.recv_timeout(wait.into()) {
+                Ok(Ok(Ok(response))) => Ok(response),
+                Ok(Ok(Err(reason))) => Err(TfError::Communication(format!("the buffer server refused the request: {}", reason))),
+                Ok(Err(err)) => Err(TfError::Communication(format!("could not call the buffer server: {}", err))),
+                Err(_) => Err(TfError::Timeout)
+            }
+        }))
+    }
+
+    /// Sends the requests with `call`, which hands them to a server over another transport.
+    pub fn with_call<F>(call: F) -> Self
+    where F: Fn(msg::LookupTransformRequest) -> Result<msg::LookupTransformResponse, TfError> + Send + Sync + 'static {
+        TfBufferClient{call: Box::new(call)}
+    }
+
+    /// Looks up a transform, letting the server wait up to `timeout` for it to become available.
+    pub fn lookup_transform_with_timeout(&self, target_frame: &str, source_frame: &str, time: Time, timeout: Duration) -> Result<msg::TransformStamped, TfError> {
+        self.request(msg::LookupTransformRequest {
+            target_frame: target_frame.to_string(),
+            source_frame: source_frame.to_string(),
+            source_time: time,
+            timeout,
+            ..Default::default()
+        })
+    }
+
+    fn request(&self, request: msg::LookupTransformRequest) -> Result<msg::TransformStamped, TfError> {
+        let response = (self.call)(request)?;
+        match response.error.error {
+            msg::TF2Error::NO_ERROR => Ok(response.transform),
+            _ => Err(from_tf2_error(response.error, response.tf_error))
+        }
+    }
+}
+
+/// Whether a lookup failed because the transform is not available, rather than because of the
+/// request or the server.
+fn is_unavailable(err: &TfError) -> bool {
+    !matches!(err, TfError::InvalidArgument(_) | TfError::CouldNotAcquireLock | TfError::Timeout | TfError::Communication(_))
+}
+
+impl TransformInterface for TfBufferClient {
+    /// Looks up a transform with the transforms the server received so far, without waiting.
+    fn lookup_transform(&self, target_frame: &str, source_frame: &str, time: Time) -> Result<msg::TransformStamped, TfError> {
+        self.lookup_transform_with_timeout(target_frame, source_frame, time, Duration::default())
+    }
+
+    /// Checks whether a transform can be looked up, letting the server wait up to `timeout` for it.
+    fn can_transform(&self, target_frame: &str, source_frame: &str, time: Time, timeout: Duration) -> Result<bool, TfError> {
+        match self.lookup_transform_with_timeout(target_frame, source_frame, time, timeout) {
+            Ok(_) => Ok(true),
+            Err(err) if is_unavailable(&err) => Ok(false),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+impl TransformWithTimeInterface for TfBufferClient {
+    fn lookup_transform_with_time_travel(&self, target_frame: &str, target_time: Time, source_frame: &str, source_time: Time,
+        fixed_frame: &str, timeout: Duration) -> Result<msg::TransformStamped, TfError> {
+        self.request(msg::LookupTransformRequest {
+            target_frame: target_frame.to_string(),
+            source_frame: source_frame.to_string(),
+            source_time,
+            timeout,
+            target_time,
+            fixed_frame: fixed_frame.to_string(),
+            advanced: true
+        })
+    }
+
+    fn can_transform_with_time_travel(&self, target_frame: &str, target_time: Time, source_frame: &str, source_time: Time,
+        fixed_frame: &str, timeout: Duration) -> Result<bool, TfError> {
+        match self.lookup_transform_with_time_travel(target_frame, target_time, source_frame, source_time, fixed_frame, timeout) {
+            Ok(_) => Ok(true),
+            Err(err) if is_unavailable(&err) => Ok(false),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_message;
+    use crate::clock::{
+        Clock,
+        ManualClock
+    };
+
+    /// Tests lookups through a client calling a server in process, errors and waits included
+    #[test]
+    fn test_buffer_server() {
+        let clock = ManualClock::new(Time{sec: 100, nsec: 0});
+        let buffer = Arc::new(RwLock::new(TfBuffer::with_clock(Arc::new(clock.clone()))));
+        let server = TfBufferServer::unadvertised(buffer.clone());
+        let client = TfBufferClient::with_call(move |request| Ok(server.handle_request(request)));
+        let time = Time{sec: 0, nsec: 0};
+
+        assert!(!client.can_transform("world", "camera", time, Duration::from_seconds(1)).unwrap());
+        assert_eq!(clock.now(), Time{sec: 101, nsec: 0});
+        assert!(matches!(client.lookup_transform("", "camera", time), Err(TfError::InvalidArgument(_))));
+
+        {
+            let mut buffer = buffer.write().unwrap();
+            buffer.handle_incoming_transforms(make_message("world", "camera", 0, 1f64, 0f64), true, "test").unwrap();
+            buffer.handle_incoming_transforms(make_message("world", "base_link", 10, 1f64, 0f64), false, "test").unwrap();
+        }
+        let transform = client.lookup_transform("world", "camera", time).unwrap();
+        assert_eq!(transform, buffer.read().unwrap().lookup_transform("world", "camera", time).unwrap());
+        assert!(matches!(client.lookup_transform("world", "base_link", Time{sec: 20, nsec: 0}), Err(TfError::AttemptedLookUpInFuture)));
+        assert!(client.can_transform_with_time_travel("camera", time, "base_link", Time{sec: 10, nsec: 0}, "world", Duration::default()).unwrap());
+
+        let unreachable = TfBufferClient::with_call(|_| Err(TfError::Communication("no route to host".to_string())));
+        assert!(matches!(unreachable.can_transform("world", "camera", time, Duration::default()), Err(TfError::Communication(_))));
+    }
+
+    /// Tests that every error is carried to the client as the variant the server met
+    #[test]
+    fn test_tf2_error() {
+        let errors = vec!(TfError::AttemptedLookupInPast, TfError::AttemptedLookUpInFuture, TfError::CouldNotFindTransform,
+            TfError::MaxDepthExceeded, TfError::CouldNotAcquireLock, TfError::LoopDetected("loop".to_string()),
+            TfError::InvalidArgument("empty frame id".to_string()), TfError::Timeout, TfError::Communication("refused".to_string()));
+        let mut tf2_errors = Vec::new();
+        for err in errors {
+            let (error, tf_error) = to_tf2_error(&err);
+            assert!(!tf2_errors.contains(&error), "{:?}", error);
+            tf2_errors.push(error.clone());
+            assert_eq!(format!("{:?}", from_tf2_error(error, tf_error)), format!("{:?}", err));
+        }
+
+        // The variant does not depend on the message, and errors of tf2 servers are approximated
+        let error = msg::TF2Error{error: msg::TF2Error::EXTRAPOLATION_ERROR, error_string: "past".to_string()};
+        assert!(matches!(from_tf2_error(error, ATTEMPTED_LOOKUP_IN_FUTURE), TfError::AttemptedLookUpInFuture));
+        let error = msg::TF2Error{error: msg::TF2Error::LOOKUP_ERROR, error_string: "\"map\" passed to lookupTransform does not exist".to_string()};
+        assert!(matches!(from_tf2_error(error, NO_TF_ERROR), TfError::CouldNotFindTransform));
+        let error = msg::TF2Error{error: msg::TF2Error::TIMEOUT_ERROR, error_string: String::new()};
+        assert!(matches!(from_tf2_error(error, NO_TF_ERROR), TfError::Timeout));
+    }
+}
@@ -1,65 +1,52 @@
+use std::mem;
+
+use nalgebra::geometry;
+
 use crate::transforms;
 use crate::core::TfError;
-use crate::utils::{
-    to_transform_stamped,
-    get_nanos
-};
+use crate::utils::get_nanos;
 use crate::msg;
+use crate::time::Time;
 
 
 #[derive(Clone, Debug)] 
 pub struct TfIndividualTransformChain {
     buffer_size: usize,
-    static_tf: bool,
+    authority: String,
     //TODO:  Implement a circular buffer. Current method is slowww.
     transform_chain: Vec<msg::TransformStamped>
 }
 
 
 impl TfIndividualTransformChain {
-    pub fn new(static_tf: bool) -> Self {
-        return TfIndividualTransformChain{buffer_size: 100, transform_chain:Vec::new(), static_tf: static_tf};
+    pub fn new() -> Self {
+        TfIndividualTransformChain{buffer_size: 100, authority: String::new(), transform_chain:Vec::new()}
     }
 
-    pub fn add_to_buffer(&mut self, msg: msg::TransformStamped) {
-        
+    /// Adds a transform to the history, `authority` being the node that published it.
+    ///
+    /// Returns the stamp of the oldest transform if it had to be dropped to make room.
+    pub fn add_to_buffer(&mut self, msg: msg::TransformStamped, authority: &str) -> Option<Time> {
+        if self.authority != authority {
+            self.authority = authority.to_string();
+        }
+
         let res = self.transform_chain.binary_search(&msg);
         
         match res {
-            Ok(x) => self.transform_chain.insert(x, msg),
-            Err(x) => self.transform_chain.insert(x, msg)
+            Ok(x) | Err(x) => self.transform_chain.insert(x, msg)
         }
 
         if self.transform_chain.len() > self.buffer_size {
-            self.transform_chain.remove(0);
+            return Some(self.transform_chain.remove(0).header.stamp);
         }
+        None
     }
 
-    pub fn get_closest_transform(&self, time: rosrust::Time) -> Result<msg::TransformStamped, TfError> {
-        if self.static_tf {
-            return Ok(self.transform_chain.get(self.transform_chain.len()-1).unwrap().clone());
-        }
-
-        let res = msg::TransformStamped {
-            child_frame_id: "".to_string(),
-            header: msg::Header {
-                frame_id: "".to_string(),
-                stamp: time,
-                seq: 1
-            },
-            transform: msg::Transform{
-                rotation: msg::Quaternion{
-                    x: 0f64, y: 0f64, z: 0f64, w: 1f64
-                },
-                translation: msg::Vector3{
-                    x: 0f64, y: 0f64, z: 0f64
-                }
-            }
-        };
-
-        let res = self.transform_chain.binary_search(&res);
+    pub fn get_closest_isometry(&self, time: Time) -> Result<geometry::Isometry3<f64>, TfError> {
+        let res = self.transform_chain.binary_search_by(|tf| tf.header.stamp.cmp(&time));
         match res {
-            Ok(x)=> return Ok(self.transform_chain.get(x).unwrap().clone()),
+            Ok(x)=> Ok(self.transform_chain[x].transform.clone().into()),
             Err(x)=> {
                 if x == 0 {
                     return Err(TfError::AttemptedLookupInPast);
@@ -67,19 +54,126 @@ impl TfIndividualTransformChain {
                 if x >= self.transform_chain.len() {
                     return Err(TfError::AttemptedLookUpInFuture)
                 }
-                let tf1 = self.transform_chain.get(x-1).unwrap().clone();
-                let tf2 = self.transform_chain.get(x).unwrap().clone();
-                let time1 = self.transform_chain.get(x-1).unwrap().header.stamp;
-                let time2 = self.transform_chain.get(x).unwrap().header.stamp;
-                let header = self.transform_chain.get(x).unwrap().header.clone();
-                let child_frame = self.transform_chain.get(x).unwrap().child_frame_id.clone();
-                let total_duration = get_nanos(time2 - time1) as f64;
-                let desired_duration = get_nanos(time - time1) as f64;
-                let weight = 1.0 - desired_duration/total_duration;
-                let final_tf = transforms::interpolate(tf1.transform, tf2.transform, weight);
-                let ros_msg = to_transform_stamped(final_tf, header.frame_id, child_frame, time);
-                Ok(ros_msg)
+                let tf1 = &self.transform_chain[x-1];
+                let tf2 = &self.transform_chain[x];
+                let total_duration = get_nanos(tf2.header.stamp - tf1.header.stamp) as f64;
+                let desired_duration = get_nanos(time - tf1.header.stamp) as f64;
+                let ratio = desired_duration/total_duration;
+                Ok(transforms::interpolate_isometry(&tf1.transform.clone().into(), &tf2.transform.clone().into(), ratio))
+            }
+        }
+    }
+}
+
+impl TfIndividualTransformChain {
+    /// The node that published the latest transform added to the chain.
+    pub fn authority(&self) -> &str {
+        &self.authority
+    }
+
+    /// The transforms in the history, oldest first.
+    pub fn transforms(&self) -> &[msg::TransformStamped] {
+        &self.transform_chain
+    }
+}
+
+impl Default for TfIndividualTransformChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TfIndividualTransformChain {
+    /// Approximate number of bytes held by the chain.
+    pub fn memory_usage(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.transform_chain.capacity() * mem::size_of::<msg::TransformStamped>()
+            + self.authority.capacity()
+            + self.transform_chain.iter().map(frame_ids_capacity).sum::<usize>()
+    }
+}
+
+/// The single value of a static transform, valid at any time.
+#[derive(Clone, Debug)]
+pub struct TfStaticTransform {
+    transform: msg::TransformStamped,
+    authority: String,
+    isometry: geometry::Isometry3<f64>
+}
+
+impl TfStaticTransform {
+    pub fn new(transform: msg::TransformStamped, authority: &str) -> Self {
+        let isometry = transform.transform.clone().into();
+        TfStaticTransform{transform, authority: authority.to_string(), isometry}
+    }
+
+    /// Replaces the value of the transform, whatever the stamps of the old and new values.
+    pub fn update(&mut self, transform: msg::TransformStamped, authority: &str) {
+        *self = Self::new(transform, authority);
+    }
+
+    /// The node that published the transform.
+    pub fn authority(&self) -> &str {
+        &self.authority
+    }
+
+    pub fn transform(&self) -> &msg::TransformStamped {
+        &self.transform
+    }
+
+    pub fn get_isometry(&self) -> geometry::Isometry3<f64> {
+        self.isometry
+    }
+
+    /// Approximate number of bytes held by the transform.
+    pub fn memory_usage(&self) -> usize {
+        mem::size_of::<Self>() + self.authority.capacity() + frame_ids_capacity(&self.transform)
+    }
+}
+
+fn frame_ids_capacity(transform: &msg::TransformStamped) -> usize {
+    transform.header.frame_id.capacity() + transform.child_frame_id.capacity()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INTERPOLATION_GOLDEN: &str = include_str!("../testdata/interpolation_golden.csv");
+
+    fn parse_sample(fields: &[f64]) -> msg::TransformStamped {
+        msg::TransformStamped {
+            child_frame_id: "child".to_string(),
+            header: msg::Header {
+                frame_id: "parent".to_string(),
+                stamp: Time::from_nanos(fields[0] as i64),
+                seq: 1
+            },
+            transform: msg::Transform {
+                translation: msg::Vector3{x: fields[1], y: fields[2], z: fields[3]},
+                rotation: msg::Quaternion{x: fields[4], y: fields[5], z: fields[6], w: fields[7]}
             }
         }
     }
-}  
\ No newline at end of file
+
+    /// Checks interpolated lookups against reference values on rotating and translating
+    /// trajectories. They do not come from tf2 yet, see the header of the file
+    #[test]
+    fn test_interpolation_golden() {
+        let mut rows = 0;
+        for line in INTERPOLATION_GOLDEN.lines().filter(|line| !line.starts_with('#')) {
+            let fields: Vec<f64> = line.split(',').map(|field| field.parse().unwrap()).collect();
+            assert_eq!(fields.len(), 24);
+            let mut chain = TfIndividualTransformChain::new();
+            chain.add_to_buffer(parse_sample(&fields[0..8]), "golden");
+            chain.add_to_buffer(parse_sample(&fields[8..16]), "golden");
+            let expected: geometry::Isometry3<f64> = parse_sample(&fields[16..24]).transform.into();
+
+            let res = chain.get_closest_isometry(Time::from_nanos(fields[16] as i64)).unwrap();
+            assert!((res.translation.vector - expected.translation.vector).norm() < 1e-9, "{}", line);
+            assert!(res.rotation.angle_to(&expected.rotation) < 1e-7, "{}", line);
+            rows += 1;
+        }
+        assert!(rows > 100);
+    }
+}
@@ -0,0 +1,141 @@
+use std::cmp;
+use std::fmt;
+use std::sync::{
+    Arc,
+    Mutex
+};
+use std::thread;
+use std::time;
+
+use crate::time::{
+    Duration,
+    Time
+};
+
+
+/// How long `poll_until` sleeps between two attempts.
+pub const POLL_PERIOD: Duration = Duration{sec: 0, nsec: 10_000_000};
+
+/// Source of the current time for the buffer and the listener.
+///
+/// Every wait and timeout goes through the clock, so time dependent behaviour can be tested
+/// deterministically with a `ManualClock`.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> Time;
+    fn sleep(&self, duration: Duration);
+}
+
+/// The wall clock of the system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Time {
+        time::SystemTime::now().into()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration.into())
+    }
+}
+
+/// The clock of the ROS node, which follows `/clock` when `use_sim_time` is set.
+///
+/// ROS has to be initialized before the clock is used.
+#[cfg(feature = "ros1")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RosClock;
+
+#[cfg(feature = "ros1")]
+impl Clock for RosClock {
+    fn now(&self) -> Time {
+        rosrust::now().into()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        rosrust::sleep(duration.into())
+    }
+}
+
+/// A clock that only moves when told to. Sleeping advances it by the duration of the sleep
+/// instead of blocking.
+///
+/// Clones share the same time.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    time: Arc<Mutex<Time>>
+}
+
+impl ManualClock {
+    pub fn new(time: Time) -> Self {
+        ManualClock{time: Arc::new(Mutex::new(time))}
+    }
+
+    pub fn set(&self, time: Time) {
+        *self.time.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.time.lock().unwrap();
+        *time = *time + duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Time {
+        *self.time.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        if duration > Duration::default() {
+            self.advance(duration);
+        }
+    }
+}
+
+/// Calls `attempt` every `POLL_PERIOD` until it succeeds or `timeout` has elapsed on `clock`,
+/// in which case the error of the last attempt is returned. `attempt` is called at least once.
+pub fn poll_until<T, E, F>(clock: &dyn Clock, timeout: Duration, mut attempt: F) -> Result<T, E>
+where F: FnMut() -> Result<T, E> {
+    let deadline = clock.now() + timeout;
+    loop {
+        let err = match attempt() {
+            Ok(value) => return Ok(value),
+            Err(err) => err
+        };
+        let now = clock.now();
+        if now >= deadline {
+            return Err(err);
+        }
+        clock.sleep(cmp::min(POLL_PERIOD, deadline - now));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Tests that polling retries until success or the timeout, without blocking on a manual clock
+    #[test]
+    fn test_poll_until() {
+        let clock = ManualClock::new(Time{sec: 10, nsec: 0});
+        let mut attempts = 0;
+        let res: Result<(), usize> = poll_until(&clock, Duration::from_nanos(25_000_000), || {
+            attempts += 1;
+            Err(attempts)
+        });
+        assert_eq!(res, Err(4));
+        assert_eq!(clock.now(), Time{sec: 10, nsec: 25_000_000});
+
+        let start = clock.now();
+        let res = poll_until(&clock, Duration::from_seconds(1), || match clock.now() - start >= Duration::from_nanos(50_000_000) {
+            true => Ok(clock.now()),
+            false => Err(())
+        });
+        assert_eq!(res, Ok(Time{sec: 10, nsec: 75_000_000}));
+
+        let res: Result<(), ()> = poll_until(&clock, Duration::default(), || Err(()));
+        assert!(res.is_err());
+        assert_eq!(clock.now(), Time{sec: 10, nsec: 75_000_000});
+    }
+}
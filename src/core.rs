@@ -1,5 +1,5 @@
-use rosrust::Time;
-use rosrust::Duration;
+use crate::time::Time;
+use crate::time::Duration;
 use std::result::Result;
 
 use crate::msg;
@@ -14,17 +14,24 @@ pub enum TfError {
     AttemptedLookUpInFuture, 
     /// There is no path between the from and to frame.
     CouldNotFindTransform,
+    /// The path between the from and to frame is longer than the maximum depth of the tree.
+    MaxDepthExceeded,
     /// In the event that a write is simultaneously happening with a read of the same tf buffer
-    CouldNotAcquireLock
+    CouldNotAcquireLock,
+    /// The transform was refused because it would close a loop in the frame tree.
+    LoopDetected(String),
+    /// A frame id or transform handed to the buffer is malformed.
+    InvalidArgument(String),
+    /// A buffer server did not answer within the timeout of the request.
+    Timeout,
+    /// A buffer server could not be reached or refused the request.
+    Communication(String)
 }
 
 
 pub trait TransformInterface {
     fn lookup_transform(&self, target_frame: &str, source_frame: &str, time: Time) -> Result<msg::TransformStamped, TfError>;
     fn can_transform(&self, target_frame: &str, source_frame: &str, time: Time, timeout: Duration) -> Result<bool, TfError>;
-
-    fn transform_to_output<'a, T>(&self, input: &'a T, output: &'a T, target_frame: &str, timeout: Option<Duration>) -> &'a T;
-    fn transform_from_input<T>(&self, input: T, target: &str, timeout: Option<Duration>) -> T;
 }
 
 pub trait TransformWithTimeInterface {
@@ -0,0 +1,268 @@
+use nalgebra::base::{
+    Matrix3,
+    Matrix6,
+    Vector3
+};
+use nalgebra::geometry;
+
+use crate::buffer::TfBuffer;
+use crate::core::{
+    TransformInterface,
+    TfError
+};
+use crate::msg;
+use crate::transforms;
+
+
+/// Returns the adjoint of an SE3 transform, the 6x6 matrix moving a twist ordered as
+/// (linear, angular) from the child frame of the transform to its parent frame.
+pub fn adjoint(isometry: &geometry::Isometry3<f64>) -> Matrix6<f64> {
+    let rotation = isometry.rotation.to_rotation_matrix().into_inner();
+    let translation = isometry.translation.vector;
+    let mut adjoint = Matrix6::zeros();
+    adjoint.fixed_slice_mut::<3, 3>(0, 0).copy_from(&rotation);
+    adjoint.fixed_slice_mut::<3, 3>(0, 3).copy_from(&(translation.cross_matrix() * rotation));
+    adjoint.fixed_slice_mut::<3, 3>(3, 3).copy_from(&rotation);
+    adjoint
+}
+
+/// Moves the covariance of `next`, an edge chained after `isometry`, to the frame of `isometry`
+/// and adds it to `covariance`.
+///
+/// Covariances are taken over left perturbations of the transforms, to first order this is how
+/// uncertainties add up along a chain of transforms.
+pub fn compose_covariance(covariance: &Matrix6<f64>, isometry: &geometry::Isometry3<f64>, next: &Matrix6<f64>) -> Matrix6<f64> {
+    let adjoint = adjoint(isometry);
+    covariance + adjoint * next * adjoint.transpose()
+}
+
+/// Returns the 6x6 matrix rotating both the linear and angular blocks by the rotation of the
+/// transform, the translation plays no part.
+fn block_rotation(isometry: &geometry::Isometry3<f64>) -> Matrix6<f64> {
+    let rotation: Matrix3<f64> = isometry.rotation.to_rotation_matrix().into_inner();
+    let mut block_rotation = Matrix6::zeros();
+    block_rotation.fixed_slice_mut::<3, 3>(0, 0).copy_from(&rotation);
+    block_rotation.fixed_slice_mut::<3, 3>(3, 3).copy_from(&rotation);
+    block_rotation
+}
+
+/// Computes `jacobian * covariance * jacobian^T` on a row-major ROS covariance.
+fn propagate(covariance: &[f64; 36], jacobian: &Matrix6<f64>) -> [f64; 36] {
+    let covariance = Matrix6::from_row_slice(covariance);
+    let propagated = jacobian * covariance * jacobian.transpose();
+    let mut result = [0f64; 36];
+    for (i, value) in result.iter_mut().enumerate() {
+        *value = propagated[(i / 6, i % 6)];
+    }
+    result
+}
+
+/// Moves a pose covariance to the parent frame of `transform`.
+///
+/// A pose covariance is expressed along the axes of its frame, both its position and its fixed
+/// axis rotation blocks are rotated while the translation of the transform leaves it unchanged.
+/// This matches `transformCovariance` of tf2_geometry_msgs.
+///
+/// Unlike a twist, the adjoint does not apply: the uncertainty of a pose is taken around the pose
+/// itself, which moves along with the frame. The translation term of the adjoint is the lever arm
+/// of a velocity measured at the origin of the frame, a pose covariance has none.
+pub fn transform_pose_covariance(covariance: &[f64; 36], transform: &msg::Transform) -> [f64; 36] {
+    let isometry = transforms::isometry_from_transform_msg(transform.clone());
+    propagate(covariance, &block_rotation(&isometry))
+}
+
+/// Moves a twist covariance to the parent frame of `transform` with the adjoint of the
+/// transform, the same map that moves the twist itself.
+pub fn transform_twist_covariance(covariance: &[f64; 36], transform: &msg::Transform) -> [f64; 36] {
+    let isometry = transforms::isometry_from_transform_msg(transform.clone());
+    propagate(covariance, &adjoint(&isometry))
+}
+
+/// Moves a twist to the parent frame of `transform`.
+///
+/// The twist is treated as the velocity of a rigid body, the linear velocity is taken at the
+/// origin of the new frame.
+pub fn transform_twist(twist: &msg::Twist, transform: &msg::Transform) -> msg::Twist {
+    let isometry = transforms::isometry_from_transform_msg(transform.clone());
+    let msg::Vector3{x, y, z} = twist.linear;
+    let linear = Vector3::new(x, y, z);
+    let msg::Vector3{x, y, z} = twist.angular;
+    let angular = isometry.rotation * Vector3::new(x, y, z);
+    let linear = isometry.rotation * linear + isometry.translation.vector.cross(&angular);
+    msg::Twist {
+        linear: msg::Vector3{x: linear[0], y: linear[1], z: linear[2]},
+        angular: msg::Vector3{x: angular[0], y: angular[1], z: angular[2]}
+    }
+}
+
+impl TfBuffer {
+    /// Moves a pose and its covariance into `target_frame`.
+    pub fn transform_pose_with_covariance(&self, target_frame: &str, pose: &msg::PoseWithCovarianceStamped) -> Result<msg::PoseWithCovarianceStamped, TfError> {
+        let transform = self.lookup_transform(target_frame, &pose.header.frame_id, pose.header.stamp)?;
+        let isometry = transforms::isometry_from_transform_msg(transform.transform.clone());
+        let pose_isometry: geometry::Isometry3<f64> = pose.pose.pose.clone().into();
+        Ok(msg::PoseWithCovarianceStamped {
+            header: stamped_header(transform.header, &pose.header),
+            pose: msg::PoseWithCovariance {
+                pose: (isometry * pose_isometry).into(),
+                covariance: transform_pose_covariance(&pose.pose.covariance, &transform.transform)
+            }
+        })
+    }
+
+    /// Moves a twist and its covariance into `target_frame`.
+    pub fn transform_twist_with_covariance(&self, target_frame: &str, twist: &msg::TwistWithCovarianceStamped) -> Result<msg::TwistWithCovarianceStamped, TfError> {
+        let transform = self.lookup_transform(target_frame, &twist.header.frame_id, twist.header.stamp)?;
+        Ok(msg::TwistWithCovarianceStamped {
+            header: stamped_header(transform.header, &twist.header),
+            twist: msg::TwistWithCovariance {
+                twist: transform_twist(&twist.twist.twist, &transform.transform),
+                covariance: transform_twist_covariance(&twist.twist.covariance, &transform.transform)
+            }
+        })
+    }
+}
+
+fn stamped_header(transform_header: msg::Header, input_header: &msg::Header) -> msg::Header {
+    msg::Header {
+        frame_id: transform_header.frame_id,
+        stamp: input_header.stamp,
+        seq: input_header.seq
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::time::Time;
+
+    fn yaw_transform() -> msg::Transform {
+        msg::Transform {
+            translation: msg::Vector3{x: 1f64, y: 2f64, z: 3f64},
+            rotation: msg::Quaternion{x: 0f64, y: 0f64, z: 0.5f64.sqrt(), w: 0.5f64.sqrt()}
+        }
+    }
+
+    fn test_covariance() -> [f64; 36] {
+        let mut covariance = [0f64; 36];
+        for i in 0..6 {
+            covariance[i * 6 + i] = (i + 1) as f64;
+        }
+        covariance[1] = 0.5f64;
+        covariance[6] = 0.5f64;
+        covariance[5 * 6] = 0.25f64;
+        covariance[5] = 0.25f64;
+        covariance
+    }
+
+    fn assert_covariance_eq(covariance: &[f64; 36], expected: &[f64; 36]) {
+        for (value, expected) in covariance.iter().zip(expected.iter()) {
+            assert!((value - expected).abs() < 1e-9, "{:?} != {:?}", covariance, expected);
+        }
+    }
+
+    /// `test_covariance` moved by `yaw_transform` the way `transformCovariance` of
+    /// tf2_geometry_msgs does it, worked out by hand: a 90 degree yaw swaps the x and y blocks and
+    /// flips the sign of their cross terms, the translation has no effect.
+    const YAW_POSE_COVARIANCE: [f64; 36] = [
+        2f64, -0.5f64, 0f64, 0f64, 0f64, 0f64,
+        -0.5f64, 1f64, 0f64, 0f64, 0f64, 0.25f64,
+        0f64, 0f64, 3f64, 0f64, 0f64, 0f64,
+        0f64, 0f64, 0f64, 5f64, 0f64, 0f64,
+        0f64, 0f64, 0f64, 0f64, 4f64, 0f64,
+        0f64, 0.25f64, 0f64, 0f64, 0f64, 6f64
+    ];
+
+    /// `test_covariance` moved by the adjoint of `yaw_transform`, worked out by hand: the
+    /// translation (1, 2, 3) adds the lever arm of the angular velocity to the linear block.
+    const YAW_TWIST_COVARIANCE: [f64; 36] = [
+        62f64, -12f64, -12f64, 0f64, -12f64, 12f64,
+        -12f64, 51.5f64, -30f64, 15f64, 0f64, -5.75f64,
+        -12f64, -30f64, 27f64, -10f64, 4f64, 0f64,
+        0f64, 15f64, -10f64, 5f64, 0f64, 0f64,
+        -12f64, 0f64, 4f64, 0f64, 4f64, 0f64,
+        12f64, -5.75f64, 0f64, 0f64, 0f64, 6f64
+    ];
+
+    /// Tests a pose covariance against the reference values of tf2_geometry_msgs
+    #[test]
+    fn test_pose_covariance() {
+        let covariance = transform_pose_covariance(&test_covariance(), &yaw_transform());
+        assert_covariance_eq(&covariance, &YAW_POSE_COVARIANCE);
+    }
+
+    /// Tests that a twist and its covariance go through the adjoint together
+    #[test]
+    fn test_twist_with_covariance() {
+        let mut buffer = TfBuffer::new();
+        let transform = msg::TransformStamped {
+            child_frame_id: "base_link".to_string(),
+            header: msg::Header {
+                frame_id: "odom".to_string(),
+                stamp: Time{sec: 0, nsec: 0},
+                seq: 1
+            },
+            transform: yaw_transform()
+        };
+        buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(transform)}, true, "test").unwrap();
+        let twist = msg::TwistWithCovarianceStamped {
+            header: msg::Header {
+                frame_id: "base_link".to_string(),
+                stamp: Time{sec: 0, nsec: 0},
+                seq: 3
+            },
+            twist: msg::TwistWithCovariance {
+                twist: msg::Twist {
+                    linear: msg::Vector3{x: 1f64, y: 0f64, z: 0f64},
+                    angular: msg::Vector3{x: 0f64, y: 0f64, z: 1f64}
+                },
+                covariance: test_covariance()
+            }
+        };
+        let res = buffer.transform_twist_with_covariance("odom", &twist).unwrap();
+        assert_eq!(res.header.frame_id, "odom");
+        assert_eq!(res.header.seq, 3);
+
+        let linear = &res.twist.twist.linear;
+        assert!((linear.x - 2f64).abs() < 1e-9);
+        assert!((linear.y - 0f64).abs() < 1e-9);
+        assert!((linear.z - 0f64).abs() < 1e-9);
+        assert_covariance_eq(&res.twist.covariance, &YAW_TWIST_COVARIANCE);
+    }
+
+    /// Tests that the pose itself is moved along with its covariance
+    #[test]
+    fn test_pose_with_covariance() {
+        let mut buffer = TfBuffer::new();
+        let transform = msg::TransformStamped {
+            child_frame_id: "base_link".to_string(),
+            header: msg::Header {
+                frame_id: "map".to_string(),
+                stamp: Time{sec: 0, nsec: 0},
+                seq: 1
+            },
+            transform: yaw_transform()
+        };
+        buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(transform)}, true, "test").unwrap();
+        let pose = msg::PoseWithCovarianceStamped {
+            header: msg::Header {
+                frame_id: "base_link".to_string(),
+                stamp: Time{sec: 0, nsec: 0},
+                seq: 2
+            },
+            pose: msg::PoseWithCovariance {
+                pose: msg::Pose {
+                    position: msg::Point{x: 1f64, y: 0f64, z: 0f64},
+                    orientation: msg::Quaternion{x: 0f64, y: 0f64, z: 0f64, w: 1f64}
+                },
+                covariance: test_covariance()
+            }
+        };
+        let res = buffer.transform_pose_with_covariance("map", &pose).unwrap();
+        let position = &res.pose.pose.position;
+        assert!((position.x - 1f64).abs() < 1e-9);
+        assert!((position.y - 3f64).abs() < 1e-9);
+        assert!((position.z - 3f64).abs() < 1e-9);
+        assert_covariance_eq(&res.pose.covariance, &YAW_POSE_COVARIANCE);
+    }
+}
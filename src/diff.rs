@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::buffer::TfBuffer;
+use crate::msg;
+use crate::transforms;
+
+
+/// How far apart the values of a static transform can be before `TfBuffer::diff` reports them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiffTolerances {
+    /// In meters.
+    pub translation: f64,
+    /// In radians.
+    pub rotation: f64
+}
+
+impl Default for DiffTolerances {
+    fn default() -> Self {
+        DiffTolerances{translation: 1e-6, rotation: 1e-6}
+    }
+}
+
+/// A frame whose parent is not the same in both trees. A frame without parent is a root.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParentChange {
+    pub frame_id: String,
+    pub old_parent: Option<String>,
+    pub new_parent: Option<String>
+}
+
+/// A static transform whose value differs between both trees.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StaticTransformChange {
+    pub parent_frame: String,
+    pub child_frame: String,
+    pub old_transform: msg::Transform,
+    pub new_transform: msg::Transform,
+    /// Distance between the translations of both values, in meters.
+    pub translation_distance: f64,
+    /// Angle of the rotation between both values, in radians.
+    pub rotation_distance: f64
+}
+
+/// The differences between two frame trees, as returned by `TfBuffer::diff`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TfDiff {
+    pub added_frames: Vec<String>,
+    pub removed_frames: Vec<String>,
+    pub reparented_frames: Vec<ParentChange>,
+    pub changed_static_transforms: Vec<StaticTransformChange>
+}
+
+impl TfDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_frames.is_empty()
+            && self.removed_frames.is_empty()
+            && self.reparented_frames.is_empty()
+            && self.changed_static_transforms.is_empty()
+    }
+}
+
+impl fmt::Display for TfDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for frame in &self.added_frames {
+            writeln!(f, "+ {}", frame)?;
+        }
+        for frame in &self.removed_frames {
+            writeln!(f, "- {}", frame)?;
+        }
+        for change in &self.reparented_frames {
+            writeln!(f, "~ {}: parent {} -> {}", change.frame_id,
+                change.old_parent.as_deref().unwrap_or("(none)"),
+                change.new_parent.as_deref().unwrap_or("(none)"))?;
+        }
+        for change in &self.changed_static_transforms {
+            writeln!(f, "! {} -> {}: static transform moved by {} m and {} rad", change.parent_frame,
+                change.child_frame, change.translation_distance, change.rotation_distance)?;
+        }
+        Ok(())
+    }
+}
+
+/// What the diff looks at in a tree: the parent of each frame and the value of the static edges.
+struct TreeSummary<'a> {
+    frames: BTreeSet<&'a str>,
+    parents: BTreeMap<&'a str, (&'a str, Option<&'a msg::Transform>)>
+}
+
+impl<'a> TreeSummary<'a> {
+    fn new(buffer: &'a TfBuffer) -> Self {
+        let mut frames = BTreeSet::new();
+        let mut parents = BTreeMap::new();
+        for edge in buffer.tree_edges() {
+            frames.insert(edge.parent_frame);
+            frames.insert(edge.child_frame);
+            let static_transform = match edge.static_tf {
+                true => edge.transforms.first().map(|transform| &transform.transform),
+                false => None
+            };
+            parents.insert(edge.child_frame, (edge.parent_frame, static_transform));
+        }
+        TreeSummary{frames, parents}
+    }
+}
+
+impl TfBuffer {
+    /// Compares the frame tree of the buffer with the one of `other`, taking the buffer as the
+    /// old tree. Static transforms are compared with the default tolerances.
+    pub fn diff(&self, other: &TfBuffer) -> TfDiff {
+        self.diff_with_tolerances(other, &DiffTolerances::default())
+    }
+
+    /// Compares the frame tree of the buffer with the one of `other`, taking the buffer as the
+    /// old tree.
+    ///
+    /// Static transforms are only compared when the edge is static in both trees, dynamic
+    /// transforms are expected to differ from one run to the other and are not compared.
+    pub fn diff_with_tolerances(&self, other: &TfBuffer, tolerances: &DiffTolerances) -> TfDiff {
+        let old = TreeSummary::new(self);
+        let new = TreeSummary::new(other);
+        let mut diff = TfDiff {
+            added_frames: new.frames.difference(&old.frames).map(|frame| frame.to_string()).collect(),
+            removed_frames: old.frames.difference(&new.frames).map(|frame| frame.to_string()).collect(),
+            ..TfDiff::default()
+        };
+
+        for frame in old.frames.intersection(&new.frames) {
+            let old_parent = old.parents.get(frame);
+            let new_parent = new.parents.get(frame);
+            match (old_parent, new_parent) {
+                (Some((old_parent, Some(old_transform))), Some((new_parent, Some(new_transform)))) if old_parent == new_parent => {
+                    let (translation_distance, rotation_distance) = transforms::distance(old_transform, new_transform);
+                    if translation_distance > tolerances.translation || rotation_distance > tolerances.rotation {
+                        diff.changed_static_transforms.push(StaticTransformChange {
+                            parent_frame: old_parent.to_string(),
+                            child_frame: frame.to_string(),
+                            old_transform: (*old_transform).clone(),
+                            new_transform: (*new_transform).clone(),
+                            translation_distance,
+                            rotation_distance
+                        });
+                    }
+                },
+                _ if old_parent.map(|(parent, _)| parent) != new_parent.map(|(parent, _)| parent) => {
+                    diff.reparented_frames.push(ParentChange {
+                        frame_id: frame.to_string(),
+                        old_parent: old_parent.map(|(parent, _)| parent.to_string()),
+                        new_parent: new_parent.map(|(parent, _)| parent.to_string())
+                    });
+                },
+                _ => {}
+            }
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_transform;
+
+    fn make_buffer(static_transforms: Vec<msg::TransformStamped>, dynamic_transforms: Vec<msg::TransformStamped>) -> TfBuffer {
+        let mut buffer = TfBuffer::new();
+        buffer.handle_incoming_transforms(msg::TFMessage{transforms: static_transforms}, true, "test").unwrap();
+        buffer.handle_incoming_transforms(msg::TFMessage{transforms: dynamic_transforms}, false, "test").unwrap();
+        buffer
+    }
+
+    /// Tests that added and removed frames, parent changes and moved static transforms are
+    /// reported, and that dynamic transforms and changes within the tolerances are not
+    #[test]
+    fn test_diff() {
+        let old = make_buffer(
+            vec!(make_transform("base_link", "camera", 0, 0.5, 0f64), make_transform("base_link", "laser", 0, 0.2, 0f64),
+                make_transform("base_link", "imu", 0, 0f64, 0f64)),
+            vec!(make_transform("odom", "base_link", 0, 1f64, 0f64), make_transform("base_link", "gripper", 0, 1f64, 0f64)));
+        let new = make_buffer(
+            vec!(make_transform("base_link", "camera", 0, 0.5, 0.1), make_transform("base_link", "laser", 0, 0.2 + 1e-9, 0f64),
+                make_transform("base_link", "gps", 0, 0f64, 0f64)),
+            vec!(make_transform("odom", "base_link", 0, 3f64, 0f64), make_transform("camera", "gripper", 0, 1f64, 0f64)));
+
+        assert!(old.diff(&old).is_empty());
+        let diff = old.diff(&new);
+        assert_eq!(diff.added_frames, vec!("gps"));
+        assert_eq!(diff.removed_frames, vec!("imu"));
+        assert_eq!(diff.reparented_frames, vec!(ParentChange {
+            frame_id: "gripper".to_string(),
+            old_parent: Some("base_link".to_string()),
+            new_parent: Some("camera".to_string())
+        }));
+        assert_eq!(diff.changed_static_transforms.len(), 1);
+        let change = &diff.changed_static_transforms[0];
+        assert_eq!((change.parent_frame.as_str(), change.child_frame.as_str()), ("base_link", "camera"));
+        assert!(change.translation_distance < 1e-12);
+        assert!((change.rotation_distance - 0.1).abs() < 1e-9);
+        assert!(old.diff_with_tolerances(&new, &DiffTolerances{translation: 1e-6, rotation: 0.2}).changed_static_transforms.is_empty());
+
+        assert_eq!(format!("{}", diff).lines().collect::<Vec<&str>>(), vec!(
+            "+ gps",
+            "- imu",
+            "~ gripper: parent base_link -> camera",
+            &format!("! base_link -> camera: static transform moved by {} m and {} rad",
+                change.translation_distance, change.rotation_distance)
+        ));
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::core::TfError;
+use crate::time::Time;
+
+
+/// A change of the contents of a `TfBuffer`.
+#[derive(Clone, Debug)]
+pub enum TfEvent {
+    /// A frame appeared in the tree.
+    FrameAdded {
+        frame_id: String
+    },
+    /// A frame left the tree, either removed or left without any transform.
+    FrameRemoved {
+        frame_id: String
+    },
+    /// A frame was published with a new parent, the edge to the old parent was dropped.
+    FrameReparented {
+        frame_id: String,
+        old_parent: String,
+        new_parent: String
+    },
+    /// A static transform received a new value.
+    StaticTransformUpdated {
+        parent_frame: String,
+        child_frame: String
+    },
+    /// A transform of an incoming message was refused.
+    MessageRejected {
+        parent_frame: String,
+        child_frame: String,
+        error: TfError
+    },
+    /// The oldest transform of an edge was dropped from its history to make room for a new one.
+    CachePruned {
+        parent_frame: String,
+        child_frame: String,
+        stamp: Time
+    },
+    /// A frame was published by another node than the one that last published it.
+    AuthorityConflict {
+        frame_id: String,
+        previous_authority: String,
+        authority: String
+    }
+}
+
+/// Handle on a callback registered with `TfBuffer::subscribe`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct SubscriptionId(u64);
+
+type Callback = Arc<dyn Fn(&TfEvent) + Send + Sync>;
+
+/// The callbacks subscribed to the events of a buffer.
+///
+/// Clones share the callbacks, a cloned buffer notifies the subscribers of the original.
+#[derive(Clone, Default)]
+pub struct EventHandlers {
+    next_id: u64,
+    callbacks: Vec<(SubscriptionId, Callback)>
+}
+
+impl EventHandlers {
+    pub fn subscribe(&mut self, callback: Callback) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.callbacks.push((id, callback));
+        id
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let count = self.callbacks.len();
+        self.callbacks.retain(|(callback_id, _)| *callback_id != id);
+        self.callbacks.len() != count
+    }
+
+    /// Calls the callbacks with the event built by `event`, which is only built if there is a
+    /// subscriber.
+    pub fn emit<F: FnOnce() -> TfEvent>(&self, event: F) {
+        if self.callbacks.is_empty() {
+            return;
+        }
+        let event = event();
+        for (_, callback) in &self.callbacks {
+            callback(&event);
+        }
+    }
+}
+
+impl fmt::Debug for EventHandlers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventHandlers")
+            .field("subscribers", &self.callbacks.len())
+            .finish()
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+
+/// Compact handle on an interned frame id.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FrameId(u32);
+
+/// Maps frame ids to `FrameId`s so the buffer can key its indexes by integers.
+///
+/// An id stays valid until its frame is forgotten, after which it may be handed to another frame.
+#[derive(Clone, Debug, Default)]
+pub struct FrameInterner {
+    ids: HashMap<String, FrameId>,
+    names: Vec<String>,
+    free_ids: Vec<FrameId>
+}
+
+impl FrameInterner {
+    /// Returns the id of a frame, interning it first if it is unknown.
+    pub fn intern(&mut self, name: &str) -> FrameId {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let id = match self.free_ids.pop() {
+            Some(id) => {
+                self.names[id.0 as usize] = name.to_string();
+                id
+            },
+            None => {
+                self.names.push(name.to_string());
+                FrameId(self.names.len() as u32 - 1)
+            }
+        };
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Forgets a frame so that its id can be reused. The caller must drop every use of the id.
+    pub fn forget(&mut self, id: FrameId) {
+        let name = std::mem::take(&mut self.names[id.0 as usize]);
+        if self.ids.remove(&name).is_some() {
+            self.free_ids.push(id);
+        }
+    }
+
+    /// Returns the id of a frame without interning it.
+    pub fn get(&self, name: &str) -> Option<FrameId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: FrameId) -> &str {
+        &self.names[id.0 as usize]
+    }
+}
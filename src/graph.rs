@@ -1,13 +1,80 @@
-#[derive(Clone,Debug,Hash)] 
+use std::collections::HashMap;
+use std::sync::{
+    Arc,
+    RwLock
+};
+
+use crate::frame::FrameId;
+
+
+/// The frames on a path through the tree, the frame the path starts from excluded.
+pub type TransformPath = Arc<[FrameId]>;
+
+#[derive(Clone,Copy,Debug,Hash,PartialEq,Eq)] 
 pub struct TfGraphNode {
-    pub child: String,
-    pub parent: String
+    pub child: FrameId,
+    pub parent: FrameId
 }
 
-impl PartialEq for TfGraphNode {
-    fn eq(&self, other: &Self) -> bool {
-        self.child == other.child && self.parent == other.parent
+/// How many paths `PathCache` holds before it starts over.
+const MAX_CACHED_PATHS: usize = 4096;
+
+/// Memoizes the paths found between pairs of frames.
+///
+/// Paths only depend on the topology of the tree, the cache has to be cleared whenever an edge is
+/// added or removed. Lookups only take the lock for reading. The cache is emptied when it is full
+/// rather than growing with the square of the number of frames, paths are cheap to find again.
+#[derive(Debug, Default)]
+pub struct PathCache {
+    paths: RwLock<HashMap<(FrameId, FrameId), TransformPath>>
+}
+
+impl PathCache {
+    pub fn get(&self, from: FrameId, to: FrameId) -> Option<TransformPath> {
+        let paths = self.paths.read().unwrap();
+        paths.get(&(from, to)).cloned()
+    }
+
+    pub fn insert(&self, from: FrameId, to: FrameId, path: TransformPath) {
+        let mut paths = self.paths.write().unwrap();
+        if paths.len() >= MAX_CACHED_PATHS {
+            paths.clear();
+        }
+        paths.insert((from, to), path);
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.get_mut().unwrap().clear();
+    }
+}
+
+impl Clone for PathCache {
+    fn clone(&self) -> Self {
+        let paths = self.paths.read().unwrap();
+        PathCache{paths: RwLock::new(paths.clone())}
     }
 }
 
-impl Eq for TfGraphNode {}
\ No newline at end of file
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frame::FrameInterner;
+
+    /// Tests that the cache starts over instead of growing past its bound
+    #[test]
+    fn test_path_cache_bound() {
+        let mut frames = FrameInterner::default();
+        let ids: Vec<FrameId> = (0..100).map(|i| frames.intern(&format!("frame_{}", i))).collect();
+        let cache = PathCache::default();
+        for from in &ids {
+            for to in &ids {
+                cache.insert(*from, *to, Arc::from(vec!(*to)));
+            }
+        }
+        let paths = cache.paths.read().unwrap().len();
+        assert!(paths <= MAX_CACHED_PATHS);
+        assert_eq!(paths, ids.len() * ids.len() % MAX_CACHED_PATHS);
+        assert_eq!(cache.get(ids[99], ids[99]).as_deref(), Some(&[ids[99]][..]));
+        assert!(cache.get(ids[0], ids[0]).is_none());
+    }
+}
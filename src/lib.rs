@@ -5,25 +5,58 @@
 //! ```ignore
 //! fn main() {
 //!     rosrust::init("listener");
-//!     let listener = TfListener::new();
+//!     let listener = TfListener::new().unwrap();
 //!     
 //!     let rate = rosrust::rate(1.0);
 //!     while rosrust::is_ok() {
-//!         let tf = listener.lookup_transform("camera", "base_link", ros::Time::now());
+//!         let tf = listener.wait_for_transform("camera", "base_link", rosrust::now().into(), Duration::from_seconds(1));
 //!         println!("{:?}", tf);
 //!         rate.sleep();
 //!     }
 //! }
 //!``` 
+//!
+//! The ROS1 integration is behind the default `ros1` feature: `TfListener`, `TfBroadcaster`, the
+//! `bag` reader, the rosrust conversions of `time` and the wire format of the messages. Without it
+//! the buffer and the math only depend on nalgebra and can be used outside of ROS.
 mod core;
 mod buffer;
-mod transforms;
+pub mod transforms;
 mod graph;
+mod frame;
 mod utils;
 mod chain;
-mod msg;
+pub mod msg;
+mod namespace;
+pub mod points;
+pub mod covariance;
+pub mod events;
+pub mod diff;
+mod snapshot;
+#[cfg(feature = "ros1")]
+pub mod bag;
+pub mod mcap;
+pub mod clock;
+pub mod time;
+#[cfg(feature = "ros1")]
+mod listener;
+#[cfg(feature = "ros1")]
+mod broadcaster;
+pub mod buffer_server;
+#[cfg(test)]
+mod test_utils;
 
 
+pub use crate::core::TfError;
 pub use crate::core::TransformInterface;
 pub use crate::core::TransformWithTimeInterface;
 pub use crate::buffer::TfBuffer;
+pub use crate::namespace::NamespacedTfBuffer;
+#[cfg(feature = "ros1")]
+pub use crate::listener::TfListener;
+#[cfg(feature = "ros1")]
+pub use crate::broadcaster::TfBroadcaster;
+pub use crate::buffer_server::{
+    TfBufferClient,
+    TfBufferServer
+};
@@ -0,0 +1,157 @@
+use std::sync::{
+    Arc,
+    RwLock
+};
+
+use crate::buffer::{
+    wait_for_lookup,
+    TfBuffer
+};
+use crate::clock::{
+    Clock,
+    RosClock
+};
+use crate::core::{
+    TfError,
+    TransformInterface,
+    TransformWithTimeInterface
+};
+use crate::msg;
+use crate::time::{
+    Duration,
+    Time
+};
+
+
+/// Fills a buffer with the transforms published on `/tf` and `/tf_static`.
+///
+/// The buffer is shared with the subscriber threads, lookups wait for missing transforms to
+/// arrive until their timeout has elapsed on the clock of the buffer.
+pub struct TfListener {
+    buffer: Arc<RwLock<TfBuffer>>,
+    _static_subscriber: rosrust::Subscriber,
+    _dynamic_subscriber: rosrust::Subscriber
+}
+
+impl TfListener {
+    /// Subscribes to the transforms, following the clock of the ROS node. ROS has to be
+    /// initialized.
+    pub fn new() -> rosrust::error::Result<Self> {
+        Self::with_clock(Arc::new(RosClock))
+    }
+
+    /// Subscribes to the transforms, reading the current time from `clock`.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> rosrust::error::Result<Self> {
+        let buffer = Arc::new(RwLock::new(TfBuffer::with_clock(clock)));
+        let static_buffer = buffer.clone();
+        let _static_subscriber = rosrust::subscribe_with_ids("/tf_static", 100, move |message: msg::TFMessage, authority: &str| {
+            Self::handle_message(&static_buffer, message, true, authority);
+        })?;
+        let dynamic_buffer = buffer.clone();
+        let _dynamic_subscriber = rosrust::subscribe_with_ids("/tf", 100, move |message: msg::TFMessage, authority: &str| {
+            Self::handle_message(&dynamic_buffer, message, false, authority);
+        })?;
+        Ok(TfListener{buffer, _static_subscriber, _dynamic_subscriber})
+    }
+
+    fn handle_message(buffer: &RwLock<TfBuffer>, message: msg::TFMessage, static_tf: bool, authority: &str) {
+        let mut buffer = buffer.write().unwrap();
+        if let Err(err) = buffer.handle_incoming_transforms(message, static_tf, authority) {
+            log::warn!("Refused a transform from {}: {:?}", authority, err);
+        }
+    }
+
+    /// The buffer the listener fills.
+    pub fn buffer(&self) -> &Arc<RwLock<TfBuffer>> {
+        &self.buffer
+    }
+
+    /// Looks up a transform, waiting up to `timeout` for it to become available.
+    pub fn wait_for_transform(&self, target_frame: &str, source_frame: &str, time: Time, timeout: Duration) -> Result<msg::TransformStamped, TfError> {
+        wait_for_lookup(&self.buffer, timeout, |buffer| buffer.lookup_transform(target_frame, source_frame, time))
+    }
+}
+
+impl TransformInterface for TfListener {
+    /// Looks up a transform with the transforms received so far, without waiting.
+    fn lookup_transform(&self, target_frame: &str, source_frame: &str, time: Time) -> Result<msg::TransformStamped, TfError> {
+        let buffer = self.buffer.read().map_err(|_| TfError::CouldNotAcquireLock)?;
+        buffer.lookup_transform(target_frame, source_frame, time)
+    }
+
+    /// Checks whether a transform can be looked up, waiting up to `timeout` for it.
+    fn can_transform(&self, target_frame: &str, source_frame: &str, time: Time, timeout: Duration) -> Result<bool, TfError> {
+        match self.wait_for_transform(target_frame, source_frame, time, timeout) {
+            Ok(_) => Ok(true),
+            Err(TfError::InvalidArgument(reason)) => Err(TfError::InvalidArgument(reason)),
+            Err(TfError::CouldNotAcquireLock) => Err(TfError::CouldNotAcquireLock),
+            Err(_) => Ok(false)
+        }
+    }
+}
+
+impl TransformWithTimeInterface for TfListener {
+    /// Looks up a transform across time through `fixed_frame`, waiting up to `timeout` for both
+    /// legs to become available.
+    fn lookup_transform_with_time_travel(&self, target_frame: &str, target_time: Time, source_frame: &str, source_time: Time,
+        fixed_frame: &str, timeout: Duration) -> Result<msg::TransformStamped, TfError> {
+        wait_for_lookup(&self.buffer, timeout, |buffer| buffer.lookup_transform_with_time_travel(
+            target_frame, target_time, source_frame, source_time, fixed_frame, Duration::default()))
+    }
+
+    fn can_transform_with_time_travel(&self, target_frame: &str, target_time: Time, source_frame: &str, source_time: Time,
+        fixed_frame: &str, timeout: Duration) -> Result<bool, TfError> {
+        match self.lookup_transform_with_time_travel(target_frame, target_time, source_frame, source_time, fixed_frame, timeout) {
+            Ok(_) => Ok(true),
+            Err(TfError::InvalidArgument(reason)) => Err(TfError::InvalidArgument(reason)),
+            Err(TfError::CouldNotAcquireLock) => Err(TfError::CouldNotAcquireLock),
+            Err(_) => Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_message;
+    use crate::clock::ManualClock;
+
+    /// Tests that waiting for a transform times out on the clock of the buffer and returns
+    /// without waiting once the transform is available
+    #[test]
+    fn test_wait_for_transform() {
+        let clock = ManualClock::new(Time{sec: 100, nsec: 0});
+        let buffer = Arc::new(RwLock::new(TfBuffer::with_clock(Arc::new(clock.clone()))));
+        let time = Time{sec: 0, nsec: 0};
+        let lookup = |target_frame: &str, source_frame: &str| wait_for_lookup(&buffer, Duration::from_seconds(2),
+            |buffer| buffer.lookup_transform(target_frame, source_frame, time));
+
+        assert!(matches!(lookup("world", "camera"), Err(TfError::CouldNotFindTransform)));
+        assert_eq!(clock.now(), Time{sec: 102, nsec: 0});
+        assert!(matches!(lookup("", "camera"), Err(TfError::InvalidArgument(_))));
+        assert_eq!(clock.now(), Time{sec: 102, nsec: 0});
+
+        TfListener::handle_message(&buffer, make_message("world", "camera", 0, 0f64, 0f64), true, "test");
+        assert!(lookup("world", "camera").is_ok());
+        assert_eq!(clock.now(), Time{sec: 102, nsec: 0});
+    }
+
+    /// Tests that time travel lookups wait for their timeout and succeed once both legs arrive
+    #[test]
+    fn test_time_travel_timeout() {
+        let clock = ManualClock::new(Time{sec: 100, nsec: 0});
+        let buffer = Arc::new(RwLock::new(TfBuffer::with_clock(Arc::new(clock.clone()))));
+        let time = Time{sec: 0, nsec: 0};
+        let lookup = || wait_for_lookup(&buffer, Duration::from_seconds(2), |buffer| buffer.lookup_transform_with_time_travel(
+            "laser", time, "camera", time, "world", Duration::default()));
+        TfListener::handle_message(&buffer, make_message("world", "camera", 0, 0f64, 0f64), true, "test");
+
+        assert!(matches!(lookup(), Err(TfError::CouldNotFindTransform)));
+        assert_eq!(clock.now(), Time{sec: 102, nsec: 0});
+
+        TfListener::handle_message(&buffer, make_message("world", "laser", 0, 0f64, 0f64), true, "test");
+        let res = lookup().unwrap();
+        assert_eq!((res.header.frame_id.as_str(), res.child_frame_id.as_str()), ("laser", "camera"));
+        assert_eq!(clock.now(), Time{sec: 102, nsec: 0});
+    }
+}
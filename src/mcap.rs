@@ -0,0 +1,474 @@
+//! Reading and writing of transforms in [MCAP](https://mcap.dev/spec) files, as recorded by
+//! rosbag2 or Foxglove.
+//!
+//! The reader scans the records in file order, so files are read whole even when they have an
+//! index. Chunks can be uncompressed or compressed with `zstd` or `lz4`. Messages of
+//! `tf2_msgs/TFMessage` encoded in `cdr` are always supported, `ros1` recordings need the `ros1`
+//! feature. Channels in other encodings are skipped with a warning.
+//!
+//! The writer produces an unchunked file with CDR messages, which Foxglove opens directly.
+use std::collections::{
+    BTreeMap,
+    HashMap
+};
+use std::convert::TryFrom;
+use std::io;
+use std::io::{
+    Read,
+    Write
+};
+
+use crate::buffer::TfBuffer;
+use crate::msg;
+use crate::msg::cdr::TF_MESSAGE_ROS2_DEFINITION;
+use crate::time::Time;
+
+
+const MCAP_MAGIC: &[u8] = b"\x89MCAP0\r\n";
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_CHUNK: u8 = 0x06;
+const OP_DATA_END: u8 = 0x0F;
+
+pub const TF_TOPIC: &str = "/tf";
+pub const TF_STATIC_TOPIC: &str = "/tf_static";
+
+/// Names of tf2_msgs/TFMessage in ROS1 and ROS 2 schemas.
+const TF_MESSAGE_SCHEMAS: &[&str] = &["tf2_msgs/TFMessage", "tf2_msgs/msg/TFMessage"];
+/// Channel metadata holding the node that published on the channel.
+const CALLERID_KEY: &str = "callerid";
+/// Message encodings `decode_message` can read.
+#[cfg(feature = "ros1")]
+const MESSAGE_ENCODINGS: &[&str] = &["cdr", "ros1"];
+#[cfg(not(feature = "ros1"))]
+const MESSAGE_ENCODINGS: &[&str] = &["cdr"];
+
+/// A message of `/tf` or `/tf_static` recorded in an MCAP file.
+#[derive(Clone, Debug)]
+pub struct McapTfMessage {
+    pub topic: String,
+    /// The node that published the message, empty when the recorder did not store it.
+    pub authority: String,
+    /// The time the message was logged at.
+    pub time: Time,
+    pub message: msg::TFMessage
+}
+
+impl McapTfMessage {
+    pub fn is_static(&self) -> bool {
+        self.topic == TF_STATIC_TOPIC
+    }
+}
+
+struct Channel {
+    topic: String,
+    authority: String,
+    message_encoding: String
+}
+
+/// Reads the messages of `/tf` and `/tf_static` recorded in an MCAP file, in file order.
+pub fn read_tf_messages<R: Read>(mut reader: R) -> io::Result<Vec<McapTfMessage>> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if magic != MCAP_MAGIC {
+        return Err(invalid_data("not an MCAP file".to_string()));
+    }
+    let mut schemas = HashMap::new();
+    let mut channels = HashMap::new();
+    let mut messages = Vec::new();
+    read_records(&mut reader, &mut schemas, &mut channels, &mut messages)?;
+    Ok(messages)
+}
+
+impl TfBuffer {
+    /// Builds a buffer from the transforms recorded in an MCAP file. Transforms the buffer refuses
+    /// are skipped, see `rejected_count`.
+    pub fn from_mcap<R: Read>(reader: R) -> io::Result<TfBuffer> {
+        let mut buffer = TfBuffer::new();
+        for message in read_tf_messages(reader)? {
+            let static_tf = message.is_static();
+            let _ = buffer.handle_incoming_transforms(message.message, static_tf, &message.authority);
+        }
+        Ok(buffer)
+    }
+
+    /// Writes the edges of the tree to an MCAP file, the whole history of dynamic edges included.
+    ///
+    /// Each authority gets its own channels, with the authority in their `callerid` metadata, so
+    /// `from_mcap` rebuilds the same buffer. Dynamic transforms are logged at their stamp and the
+    /// static ones at the start of the recording.
+    pub fn write_mcap<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let edges = self.tree_edges();
+        let start = edges.iter()
+            .filter(|edge| !edge.static_tf)
+            .flat_map(|edge| edge.transforms.iter().map(|transform| transform.header.stamp))
+            .min()
+            .unwrap_or_default();
+
+        let mut channels = BTreeMap::new();
+        let mut static_transforms: BTreeMap<&str, Vec<msg::TransformStamped>> = BTreeMap::new();
+        let mut dynamic_transforms = Vec::new();
+        for edge in &edges {
+            let next_id = channels.len() as u16 + 1;
+            let channel_id = *channels.entry((edge.static_tf, edge.authority)).or_insert(next_id);
+            if edge.static_tf {
+                static_transforms.entry(edge.authority).or_default().extend_from_slice(edge.transforms);
+            } else {
+                dynamic_transforms.extend(edge.transforms.iter().map(|transform| (channel_id, transform)));
+            }
+        }
+        dynamic_transforms.sort_by_key(|(_, transform)| transform.header.stamp);
+
+        writer.write_all(MCAP_MAGIC)?;
+        let mut header = Vec::new();
+        put_string(&mut header, "ros2");
+        put_string(&mut header, "rosrust_tf2");
+        write_record(&mut writer, OP_HEADER, &header)?;
+
+        let mut schema = 1u16.to_le_bytes().to_vec();
+        put_string(&mut schema, TF_MESSAGE_SCHEMAS[1]);
+        put_string(&mut schema, "ros2msg");
+        put_bytes(&mut schema, TF_MESSAGE_ROS2_DEFINITION.as_bytes());
+        write_record(&mut writer, OP_SCHEMA, &schema)?;
+
+        for ((static_tf, authority), id) in &channels {
+            let mut channel = id.to_le_bytes().to_vec();
+            channel.extend_from_slice(&1u16.to_le_bytes());
+            put_string(&mut channel, if *static_tf { TF_STATIC_TOPIC } else { TF_TOPIC });
+            put_string(&mut channel, "cdr");
+            let mut metadata = Vec::new();
+            put_string(&mut metadata, CALLERID_KEY);
+            put_string(&mut metadata, authority);
+            put_bytes(&mut channel, &metadata);
+            write_record(&mut writer, OP_CHANNEL, &channel)?;
+        }
+
+        let static_messages = static_transforms.into_iter()
+            .map(|(authority, transforms)| (channels[&(true, authority)], start, msg::TFMessage{transforms}));
+        let dynamic_messages = dynamic_transforms.into_iter()
+            .map(|(id, transform)| (id, transform.header.stamp, msg::TFMessage{transforms: vec!(transform.clone())}));
+        for (sequence, (channel_id, time, message)) in static_messages.chain(dynamic_messages).enumerate() {
+            let log_time = u64::try_from(time.nanos()).unwrap_or(0);
+            let mut record = channel_id.to_le_bytes().to_vec();
+            record.extend_from_slice(&(sequence as u32).to_le_bytes());
+            record.extend_from_slice(&log_time.to_le_bytes());
+            record.extend_from_slice(&log_time.to_le_bytes());
+            record.extend(message.encode_cdr());
+            write_record(&mut writer, OP_MESSAGE, &record)?;
+        }
+
+        // A zero CRC means it was not computed, and the footer points to no summary section.
+        write_record(&mut writer, OP_DATA_END, &0u32.to_le_bytes())?;
+        write_record(&mut writer, OP_FOOTER, &[0u8; 20])?;
+        writer.write_all(MCAP_MAGIC)
+    }
+}
+
+fn read_records<R: Read>(reader: &mut R, schemas: &mut HashMap<u16, String>, channels: &mut HashMap<u16, Channel>, messages: &mut Vec<McapTfMessage>) -> io::Result<()> {
+    while let Some((opcode, record)) = read_record(reader)? {
+        let mut fields = record.as_slice();
+        match opcode {
+            OP_SCHEMA => {
+                let id = take_u16(&mut fields)?;
+                schemas.insert(id, take_string(&mut fields)?);
+            },
+            OP_CHANNEL => {
+                let id = take_u16(&mut fields)?;
+                let schema_id = take_u16(&mut fields)?;
+                let topic = take_string(&mut fields)?;
+                let message_encoding = take_string(&mut fields)?;
+                let is_tf_message = schemas.get(&schema_id).is_some_and(|schema| TF_MESSAGE_SCHEMAS.contains(&schema.as_str()));
+                if !is_tf_message || (topic != TF_TOPIC && topic != TF_STATIC_TOPIC) {
+                    continue;
+                }
+                if !MESSAGE_ENCODINGS.contains(&message_encoding.as_str()) {
+                    log::warn!("Skipping the {} messages of {}, they cannot be decoded", message_encoding, topic);
+                    continue;
+                }
+                let mut metadata = take_bytes(&mut fields)?;
+                let mut authority = String::new();
+                while !metadata.is_empty() {
+                    let key = take_string(&mut metadata)?;
+                    let value = take_string(&mut metadata)?;
+                    if key == CALLERID_KEY {
+                        authority = value;
+                    }
+                }
+                channels.insert(id, Channel{topic, authority, message_encoding});
+            },
+            OP_MESSAGE => {
+                if let Some(channel) = channels.get(&take_u16(&mut fields)?) {
+                    take(&mut fields, 4)?;
+                    let log_time = take_u64(&mut fields)?;
+                    take(&mut fields, 8)?;
+                    messages.push(McapTfMessage {
+                        topic: channel.topic.clone(),
+                        authority: channel.authority.clone(),
+                        time: Time::from_nanos(i64::try_from(log_time).unwrap_or(i64::MAX)),
+                        message: decode_message(&channel.message_encoding, fields)?
+                    });
+                }
+            },
+            OP_CHUNK => {
+                take(&mut fields, 16)?;
+                let uncompressed_size = take_u64(&mut fields)?;
+                take(&mut fields, 4)?;
+                let compression = take_string(&mut fields)?;
+                let length = take_u64(&mut fields)? as usize;
+                let chunk_records = decompress(&compression, take(&mut fields, length)?, uncompressed_size)?;
+                read_records(&mut chunk_records.as_slice(), schemas, channels, messages)?;
+            },
+            OP_FOOTER => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Decompresses the records of a chunk, checking they have the size the chunk announces.
+fn decompress(compression: &str, data: &[u8], uncompressed_size: u64) -> io::Result<Vec<u8>> {
+    // One byte more than announced is enough to tell the size is wrong
+    let limit = uncompressed_size.saturating_add(1);
+    let mut records = Vec::new();
+    match compression {
+        "" => records.extend_from_slice(data),
+        "zstd" => {
+            let decoder = ruzstd::decoding::StreamingDecoder::new(data)
+                .map_err(|err| invalid_data(format!("invalid zstd chunk: {}", err)))?;
+            decoder.take(limit).read_to_end(&mut records)?;
+        },
+        "lz4" => {
+            lz4_flex::frame::FrameDecoder::new(data).take(limit).read_to_end(&mut records)?;
+        },
+        compression => return Err(invalid_data(format!("unsupported chunk compression {}", compression)))
+    }
+    if records.len() as u64 != uncompressed_size {
+        return Err(invalid_data(format!("chunk holds {} bytes instead of {}", records.len(), uncompressed_size)));
+    }
+    Ok(records)
+}
+
+fn decode_message(encoding: &str, data: &[u8]) -> io::Result<msg::TFMessage> {
+    match encoding {
+        "cdr" => msg::TFMessage::decode_cdr(data),
+        #[cfg(feature = "ros1")]
+        "ros1" => rosrust::RosMsg::decode(data),
+        encoding => Err(invalid_data(format!("unsupported message encoding {}", encoding)))
+    }
+}
+
+/// Reads the opcode and the content of a record, returns None at the end of the input.
+fn read_record<R: Read>(reader: &mut R) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut opcode = [0u8; 1];
+    match reader.read_exact(&mut opcode) {
+        Ok(()) => {},
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err)
+    }
+    let mut length = [0u8; 8];
+    reader.read_exact(&mut length)?;
+    let mut record = Vec::new();
+    let length = u64::from_le_bytes(length);
+    reader.take(length).read_to_end(&mut record)?;
+    if record.len() as u64 != length {
+        return Err(invalid_data("truncated record".to_string()));
+    }
+    Ok(Some((opcode[0], record)))
+}
+
+fn write_record<W: Write>(writer: &mut W, opcode: u8, record: &[u8]) -> io::Result<()> {
+    writer.write_all(&[opcode])?;
+    writer.write_all(&(record.len() as u64).to_le_bytes())?;
+    writer.write_all(record)
+}
+
+fn take<'a>(fields: &mut &'a [u8], length: usize) -> io::Result<&'a [u8]> {
+    if length > fields.len() {
+        return Err(invalid_data("truncated record field".to_string()));
+    }
+    let (field, rest) = fields.split_at(length);
+    *fields = rest;
+    Ok(field)
+}
+
+fn take_u16(fields: &mut &[u8]) -> io::Result<u16> {
+    Ok(u16::from_le_bytes(<[u8; 2]>::try_from(take(fields, 2)?).unwrap()))
+}
+
+fn take_u32(fields: &mut &[u8]) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(<[u8; 4]>::try_from(take(fields, 4)?).unwrap()))
+}
+
+fn take_u64(fields: &mut &[u8]) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(<[u8; 8]>::try_from(take(fields, 8)?).unwrap()))
+}
+
+/// Takes bytes prefixed by their u32 length, as strings, maps and schema data are.
+fn take_bytes<'a>(fields: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let length = take_u32(fields)? as usize;
+    take(fields, length)
+}
+
+fn take_string(fields: &mut &[u8]) -> io::Result<String> {
+    String::from_utf8(take_bytes(fields)?.to_vec()).map_err(|_| invalid_data("record string is not UTF-8".to_string()))
+}
+
+fn put_bytes(record: &mut Vec<u8>, bytes: &[u8]) {
+    record.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    record.extend_from_slice(bytes);
+}
+
+fn put_string(record: &mut Vec<u8>, value: &str) {
+    put_bytes(record, value.as_bytes());
+}
+
+fn invalid_data(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_transform;
+
+    const CDR_ZSTD_FIXTURE: &[u8] = include_bytes!("../testdata/tf_cdr_zstd.mcap");
+    const ROS1_LZ4_FIXTURE: &[u8] = include_bytes!("../testdata/tf_ros1_lz4.mcap");
+
+    /// Moves the records between the header and the data end of `mcap` into a chunk.
+    fn chunk_data(mcap: &[u8], compression: &str) -> Vec<u8> {
+        let data_start = MCAP_MAGIC.len() + 9 + u64::from_le_bytes(<[u8; 8]>::try_from(&mcap[9..17]).unwrap()) as usize;
+        let data_end = mcap.len() - MCAP_MAGIC.len() - 2 * 9 - 4 - 20;
+        let mut chunk = vec!(0u8; 16);
+        chunk.extend_from_slice(&((data_end - data_start) as u64).to_le_bytes());
+        chunk.extend_from_slice(&[0u8; 4]);
+        put_string(&mut chunk, compression);
+        chunk.extend_from_slice(&((data_end - data_start) as u64).to_le_bytes());
+        chunk.extend_from_slice(&mcap[data_start..data_end]);
+        let mut chunked = mcap[..data_start].to_vec();
+        write_record(&mut chunked, OP_CHUNK, &chunk).unwrap();
+        chunked.extend_from_slice(&mcap[data_end..]);
+        chunked
+    }
+
+    /// Tests that a buffer written to MCAP is read back identically, and that chunks are read
+    #[test]
+    fn test_mcap_round_trip() {
+        let mut buffer = TfBuffer::new();
+        buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(make_transform("base_link", "camera", 0, 0.5, 0.5))}, true, "/robot_state_publisher").unwrap();
+        for sec in 5..8 {
+            buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(make_transform("odom", "base_link", sec, sec as f64, 0.5))}, false, "/odometry").unwrap();
+        }
+        buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(make_transform("map", "odom", 6, 1f64, 0.5))}, false, "/localization").unwrap();
+
+        let mut mcap = Vec::new();
+        buffer.write_mcap(&mut mcap).unwrap();
+        let messages = read_tf_messages(mcap.as_slice()).unwrap();
+        assert_eq!(messages.len(), 5);
+        assert!(messages[0].is_static());
+        assert_eq!(messages[0].time, Time{sec: 5, nsec: 0});
+        assert_eq!(messages[0].authority, "/robot_state_publisher");
+        assert!(messages[1..].iter().all(|message| !message.is_static()));
+
+        let read = TfBuffer::from_mcap(mcap.as_slice()).unwrap();
+        assert!(buffer.diff(&read).is_empty());
+        assert_eq!(read.to_snapshot(), buffer.to_snapshot());
+
+        let chunked = chunk_data(&mcap, "");
+        assert_eq!(TfBuffer::from_mcap(chunked.as_slice()).unwrap().to_snapshot(), buffer.to_snapshot());
+        let compressed = chunk_data(&mcap, "zstd");
+        assert_eq!(read_tf_messages(compressed.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let compressed = chunk_data(&mcap, "bz2");
+        assert_eq!(read_tf_messages(compressed.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(read_tf_messages(&b"#ROSBAG V2.0\n"[..]).is_err());
+    }
+
+    /// Tests reading a message recorded from ROS1
+    #[cfg(feature = "ros1")]
+    #[test]
+    fn test_read_ros1_message() {
+        use rosrust::RosMsg;
+
+        let message = msg::TFMessage{transforms: vec!(make_transform("odom", "base_link", 3, 1f64, 0.5))};
+        let mut mcap = MCAP_MAGIC.to_vec();
+        let mut schema = 1u16.to_le_bytes().to_vec();
+        put_string(&mut schema, TF_MESSAGE_SCHEMAS[0]);
+        put_string(&mut schema, "ros1msg");
+        put_bytes(&mut schema, b"");
+        write_record(&mut mcap, OP_SCHEMA, &schema).unwrap();
+        let mut channel = 4u16.to_le_bytes().to_vec();
+        channel.extend_from_slice(&1u16.to_le_bytes());
+        put_string(&mut channel, TF_TOPIC);
+        put_string(&mut channel, "ros1");
+        put_bytes(&mut channel, b"");
+        write_record(&mut mcap, OP_CHANNEL, &channel).unwrap();
+        let mut record = 4u16.to_le_bytes().to_vec();
+        record.extend_from_slice(&[0u8; 4]);
+        record.extend_from_slice(&3_000_000_000u64.to_le_bytes());
+        record.extend_from_slice(&3_000_000_000u64.to_le_bytes());
+        message.encode(&mut record).unwrap();
+        write_record(&mut mcap, OP_MESSAGE, &record).unwrap();
+
+        let messages = read_tf_messages(mcap.as_slice()).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].authority, "");
+        assert_eq!(messages[0].time, Time{sec: 3, nsec: 0});
+        assert_eq!(messages[0].message, message);
+    }
+
+    /// Checks the transforms of the fixtures, see scripts/generate_mcap_fixtures.py
+    fn check_fixture(messages: &[McapTfMessage], static_authority: &str, dynamic_authority: &str) {
+        assert_eq!(messages.len(), 4);
+        assert!(messages[0].is_static());
+        assert_eq!(messages[0].authority, static_authority);
+        assert_eq!(messages[0].time, Time{sec: 1, nsec: 0});
+        assert!(messages[1..].iter().all(|message| !message.is_static() && message.authority == dynamic_authority));
+        assert_eq!(messages[2].time, Time{sec: 1, nsec: 500_000_000});
+
+        let mut buffer = TfBuffer::new();
+        for message in messages {
+            buffer.handle_incoming_transforms(message.message.clone(), message.is_static(), &message.authority).unwrap();
+        }
+        let camera = buffer.lookup_isometry("base_link", "camera", Time{sec: 5, nsec: 0}).unwrap();
+        assert!((camera.translation.vector - nalgebra::Vector3::new(0.1, 0f64, 0.3)).norm() < 1e-12);
+        let base_link = buffer.lookup_isometry("odom", "base_link", Time{sec: 1, nsec: 250_000_000}).unwrap();
+        assert!((base_link.translation.vector - nalgebra::Vector3::new(0.25, 0f64, 0f64)).norm() < 1e-12);
+        assert!((base_link.rotation.angle() - 0.05).abs() < 1e-9);
+    }
+
+    /// Tests reading a rosbag2 recording with CDR messages in zstd chunks
+    #[test]
+    fn test_read_cdr_zstd_fixture() {
+        let messages = read_tf_messages(CDR_ZSTD_FIXTURE).unwrap();
+        check_fixture(&messages, "", "");
+    }
+
+    /// Tests reading a ROS1 recording in lz4 chunks, with the publishers in the channel metadata
+    #[cfg(feature = "ros1")]
+    #[test]
+    fn test_read_ros1_lz4_fixture() {
+        let messages = read_tf_messages(ROS1_LZ4_FIXTURE).unwrap();
+        check_fixture(&messages, "/robot_state_publisher", "/odometry");
+    }
+
+    /// Tests that ROS1 channels are skipped when they cannot be decoded
+    #[cfg(not(feature = "ros1"))]
+    #[test]
+    fn test_skip_ros1_channels() {
+        assert!(read_tf_messages(ROS1_LZ4_FIXTURE).unwrap().is_empty());
+        assert!(TfBuffer::from_mcap(ROS1_LZ4_FIXTURE).unwrap().frames().is_empty());
+    }
+
+    /// Tests that chunks that do not decompress to their announced size are refused
+    #[test]
+    fn test_corrupted_chunk() {
+        let mut corrupted = CDR_ZSTD_FIXTURE.to_vec();
+        // The first chunk follows the header, its uncompressed size follows two stamps
+        let chunk = MCAP_MAGIC.len() + 9 + u64::from_le_bytes(<[u8; 8]>::try_from(&corrupted[9..17]).unwrap()) as usize;
+        assert_eq!(corrupted[chunk], OP_CHUNK);
+        corrupted[chunk + 9 + 16] ^= 1;
+        assert_eq!(read_tf_messages(corrupted.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,4 +1,24 @@
 use std::cmp::Ordering;
+use std::ops::Mul;
+
+use nalgebra::geometry;
+
+use crate::time::{
+    Duration,
+    Time
+};
+
+pub(crate) mod cdr;
+#[cfg(feature = "ros1")]
+mod ros1;
+
+#[cfg(feature = "ros1")]
+pub use self::ros1::LookupTransform;
+
+
+/// How close to +/-1 the sine of the pitch has to be for `Quaternion::to_rpy` to consider the
+/// rotation gimbal locked.
+const GIMBAL_LOCK_TOLERANCE: f64 = 1e-12;
 
 
 pub trait MessageConverter<Output=Self> {
@@ -28,7 +48,7 @@ impl Ord for  TransformStamped {
 
 impl PartialOrd for TransformStamped {
     fn partial_cmp(&self, other: &TransformStamped)  -> Option<Ordering> {
-        Some(self.header.stamp.cmp(&other.header.stamp))
+        Some(self.cmp(other))
     }
 }
 
@@ -42,7 +62,7 @@ pub struct Transform {
 pub struct Header {
     pub seq: u32,
     pub frame_id: String,
-    pub stamp: rosrust::Time
+    pub stamp: Time
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -60,7 +80,313 @@ pub struct Quaternion {
     pub w: f64
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct TFMessage {
     pub transforms: Vec<TransformStamped>,
-}
\ No newline at end of file
+}
+
+/// NewType pattern on tf2_msgs::TF2Error, the outcome of a lookup made by a buffer server.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TF2Error {
+    pub error: u8,
+    pub error_string: String
+}
+
+impl TF2Error {
+    pub const NO_ERROR: u8 = 0;
+    pub const LOOKUP_ERROR: u8 = 1;
+    pub const CONNECTIVITY_ERROR: u8 = 2;
+    pub const EXTRAPOLATION_ERROR: u8 = 3;
+    pub const INVALID_ARGUMENT_ERROR: u8 = 4;
+    pub const TIMEOUT_ERROR: u8 = 5;
+    pub const TRANSFORM_ERROR: u8 = 6;
+}
+
+/// Request sent to a buffer server, with the fields of tf2_msgs::LookupTransformGoal.
+/// `target_time` and `fixed_frame` are only used when `advanced` is set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LookupTransformRequest {
+    pub target_frame: String,
+    pub source_frame: String,
+    pub source_time: Time,
+    pub timeout: Duration,
+    pub target_time: Time,
+    pub fixed_frame: String,
+    pub advanced: bool
+}
+
+/// Response of a buffer server, with the fields of tf2_msgs::LookupTransformResult.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LookupTransformResponse {
+    pub transform: TransformStamped,
+    pub error: TF2Error,
+    /// The `TfError` variant the server met, see `buffer_server` for the codes. tf2 has no such
+    /// field, zero leaves the client to guess the variant from `error.error`.
+    pub tf_error: u8
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct Pose {
+    pub position: Point,
+    pub orientation: Quaternion
+}
+
+/**
+ * Row-major 6x6 covariance over (x, y, z, rotation about X, rotation about Y, rotation about Z)
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoseWithCovariance {
+    pub pose: Pose,
+    pub covariance: [f64; 36]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoseWithCovarianceStamped {
+    pub header: Header,
+    pub pose: PoseWithCovariance
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct Twist {
+    pub linear: Vector3,
+    pub angular: Vector3
+}
+
+/**
+ * Row-major 6x6 covariance over (linear x, y, z, angular x, y, z)
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwistWithCovariance {
+    pub twist: Twist,
+    pub covariance: [f64; 36]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwistWithCovarianceStamped {
+    pub header: Header,
+    pub twist: TwistWithCovariance
+}
+
+impl From<Vector3> for geometry::Translation3<f64> {
+    fn from(vector: Vector3) -> Self {
+        let Vector3{x, y, z} = vector;
+        geometry::Translation3::new(x, y, z)
+    }
+}
+
+impl From<geometry::Translation3<f64>> for Vector3 {
+    fn from(translation: geometry::Translation3<f64>) -> Self {
+        Vector3 {
+            x: translation.vector[0],
+            y: translation.vector[1],
+            z: translation.vector[2]
+        }
+    }
+}
+
+/// The quaternion is normalized on the way in, a message does not have to hold a unit quaternion.
+impl From<Quaternion> for geometry::UnitQuaternion<f64> {
+    fn from(quaternion: Quaternion) -> Self {
+        let Quaternion{x, y, z, w} = quaternion;
+        geometry::UnitQuaternion::new_normalize(
+            geometry::Quaternion::new(w, x, y, z)
+        )
+    }
+}
+
+impl From<geometry::UnitQuaternion<f64>> for Quaternion {
+    fn from(quaternion: geometry::UnitQuaternion<f64>) -> Self {
+        Quaternion {
+            x: quaternion.coords[0],
+            y: quaternion.coords[1],
+            z: quaternion.coords[2],
+            w: quaternion.coords[3]
+        }
+    }
+}
+
+impl From<Transform> for geometry::Isometry3<f64> {
+    fn from(transform: Transform) -> Self {
+        geometry::Isometry3::from_parts(transform.translation.into(), transform.rotation.into())
+    }
+}
+
+impl From<geometry::Isometry3<f64>> for Transform {
+    fn from(isometry: geometry::Isometry3<f64>) -> Self {
+        Transform {
+            translation: isometry.translation.into(),
+            rotation: isometry.rotation.into()
+        }
+    }
+}
+
+impl From<Point> for geometry::Point3<f64> {
+    fn from(point: Point) -> Self {
+        let Point{x, y, z} = point;
+        geometry::Point3::new(x, y, z)
+    }
+}
+
+impl From<geometry::Point3<f64>> for Point {
+    fn from(point: geometry::Point3<f64>) -> Self {
+        Point {
+            x: point[0],
+            y: point[1],
+            z: point[2]
+        }
+    }
+}
+
+impl From<Pose> for geometry::Isometry3<f64> {
+    fn from(pose: Pose) -> Self {
+        let Point{x, y, z} = pose.position;
+        geometry::Isometry3::from_parts(geometry::Translation3::new(x, y, z), pose.orientation.into())
+    }
+}
+
+impl From<geometry::Isometry3<f64>> for Pose {
+    fn from(isometry: geometry::Isometry3<f64>) -> Self {
+        Pose {
+            position: geometry::Point3::from(isometry.translation.vector).into(),
+            orientation: isometry.rotation.into()
+        }
+    }
+}
+
+impl Quaternion {
+    /// Rotation about the fixed X, Y then Z axes, the convention of tf2's `setRPY`.
+    pub fn from_rpy(roll: f64, pitch: f64, yaw: f64) -> Self {
+        geometry::UnitQuaternion::from_euler_angles(roll, pitch, yaw).into()
+    }
+
+    /// Rotation of `angle` radians about `axis`, which does not have to be normalized.
+    /// A zero axis gives the identity.
+    pub fn from_axis_angle(axis: &Vector3, angle: f64) -> Self {
+        let axis = nalgebra::Vector3::new(axis.x, axis.y, axis.z);
+        match nalgebra::Unit::try_new(axis, 0f64) {
+            Some(axis) => geometry::UnitQuaternion::from_axis_angle(&axis, angle).into(),
+            None => Quaternion{x: 0f64, y: 0f64, z: 0f64, w: 1f64}
+        }
+    }
+
+    /// Returns the (roll, pitch, yaw) angles of the rotation, the inverse of `from_rpy`.
+    ///
+    /// Follows tf2's `Matrix3x3::getRPY`: the pitch is in [-pi/2, pi/2] and when the rotation is
+    /// gimbal locked the yaw is set to zero and the roll absorbs the rotation about the vertical
+    /// axis.
+    pub fn to_rpy(&self) -> (f64, f64, f64) {
+        let rotation: geometry::UnitQuaternion<f64> = self.clone().into();
+        let m = rotation.to_rotation_matrix().into_inner();
+        if m[(2, 0)].abs() >= 1.0 - GIMBAL_LOCK_TOLERANCE {
+            if m[(2, 0)] < 0.0 {
+                (m[(0, 1)].atan2(m[(0, 2)]), std::f64::consts::FRAC_PI_2, 0.0)
+            }
+            else {
+                ((-m[(0, 1)]).atan2(-m[(0, 2)]), -std::f64::consts::FRAC_PI_2, 0.0)
+            }
+        }
+        else {
+            let pitch = -m[(2, 0)].asin();
+            let cos_pitch = pitch.cos();
+            let roll = (m[(2, 1)] / cos_pitch).atan2(m[(2, 2)] / cos_pitch);
+            let yaw = (m[(1, 0)] / cos_pitch).atan2(m[(0, 0)] / cos_pitch);
+            (roll, pitch, yaw)
+        }
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform::from_xyz_rpy(0f64, 0f64, 0f64, 0f64, 0f64, 0f64)
+    }
+
+    pub fn from_xyz_rpy(x: f64, y: f64, z: f64, roll: f64, pitch: f64, yaw: f64) -> Self {
+        Transform {
+            translation: Vector3{x, y, z},
+            rotation: Quaternion::from_rpy(roll, pitch, yaw)
+        }
+    }
+}
+
+/// Composes two transforms, `a * b` applies `b` first then `a`.
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, other: Transform) -> Transform {
+        let lhs: geometry::Isometry3<f64> = self.into();
+        let rhs: geometry::Isometry3<f64> = other.into();
+        (lhs * rhs).into()
+    }
+}
+
+impl<'a> Mul<&'a Transform> for &'a Transform {
+    type Output = Transform;
+
+    fn mul(self, other: &'a Transform) -> Transform {
+        self.clone() * other.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::{
+        FRAC_PI_2,
+        FRAC_PI_4
+    };
+
+    fn assert_rpy_eq(rpy: (f64, f64, f64), expected: (f64, f64, f64)) {
+        assert!((rpy.0 - expected.0).abs() < 1e-9, "{:?} != {:?}", rpy, expected);
+        assert!((rpy.1 - expected.1).abs() < 1e-9, "{:?} != {:?}", rpy, expected);
+        assert!((rpy.2 - expected.2).abs() < 1e-9, "{:?} != {:?}", rpy, expected);
+    }
+
+    #[test]
+    fn test_rpy_round_trip() {
+        for rpy in [(0.1f64, -0.2f64, 0.3f64), (3f64, 1.5f64, -3f64), (-1f64, -1.2f64, 2.5f64)] {
+            assert_rpy_eq(Quaternion::from_rpy(rpy.0, rpy.1, rpy.2).to_rpy(), rpy);
+        }
+    }
+
+    #[test]
+    fn test_rpy_matches_tf2_convention() {
+        // tf2 composes the fixed axis rotations as Rz(yaw) * Ry(pitch) * Rx(roll)
+        let q = Quaternion::from_rpy(FRAC_PI_2, 0f64, FRAC_PI_2);
+        let expected = Quaternion::from_axis_angle(&Vector3{x: 0f64, y: 0f64, z: 1f64}, FRAC_PI_2);
+        let expected = geometry::UnitQuaternion::from(expected)
+            * geometry::UnitQuaternion::from(Quaternion::from_axis_angle(&Vector3{x: 1f64, y: 0f64, z: 0f64}, FRAC_PI_2));
+        assert!(geometry::UnitQuaternion::from(q).angle_to(&expected) < 1e-9);
+    }
+
+    #[test]
+    fn test_rpy_gimbal_lock() {
+        assert_rpy_eq(Quaternion::from_rpy(0.3f64, FRAC_PI_2, 0.2f64).to_rpy(), (0.1f64, FRAC_PI_2, 0f64));
+        assert_rpy_eq(Quaternion::from_rpy(0.3f64, -FRAC_PI_2, 0.2f64).to_rpy(), (0.5f64, -FRAC_PI_2, 0f64));
+    }
+
+    #[test]
+    fn test_axis_angle() {
+        let q = Quaternion::from_axis_angle(&Vector3{x: 0f64, y: 0f64, z: 2f64}, FRAC_PI_2);
+        assert!((q.z - FRAC_PI_4.sin()).abs() < 1e-12);
+        assert!((q.w - FRAC_PI_4.cos()).abs() < 1e-12);
+        assert_eq!(Quaternion::from_axis_angle(&Vector3{x: 0f64, y: 0f64, z: 0f64}, 1f64), Transform::identity().rotation);
+    }
+
+    #[test]
+    fn test_transform_composition() {
+        let a = Transform::from_xyz_rpy(1f64, 0f64, 0f64, 0f64, 0f64, FRAC_PI_2);
+        let b = Transform::from_xyz_rpy(1f64, 0f64, 0f64, 0f64, 0f64, 0f64);
+        let c = &a * &b;
+        assert!((c.translation.x - 1f64).abs() < 1e-12);
+        assert!((c.translation.y - 1f64).abs() < 1e-12);
+        assert_rpy_eq(c.rotation.to_rpy(), (0f64, 0f64, FRAC_PI_2));
+        assert_eq!(a.clone() * Transform::identity(), a);
+    }
+}
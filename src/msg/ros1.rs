@@ -0,0 +1,154 @@
+//! ROS1 wire format of the messages, so they can be sent and received with rosrust.
+use std::io;
+
+use rosrust::RosMsg;
+use rosrust::rosmsg::{
+    decode_variable_vec,
+    encode_variable_slice
+};
+
+use super::{
+    Header,
+    LookupTransformRequest,
+    LookupTransformResponse,
+    Quaternion,
+    TF2Error,
+    TFMessage,
+    Transform,
+    TransformStamped,
+    Vector3
+};
+
+
+/// Full definition of tf2_msgs/TFMessage, as sent in connection headers.
+const TF_MESSAGE_DEFINITION: &str = "geometry_msgs/TransformStamped[] transforms
+
+================================================================================
+MSG: geometry_msgs/TransformStamped
+Header header
+string child_frame_id
+Transform transform
+
+================================================================================
+MSG: std_msgs/Header
+uint32 seq
+time stamp
+string frame_id
+
+================================================================================
+MSG: geometry_msgs/Transform
+Vector3 translation
+Quaternion rotation
+
+================================================================================
+MSG: geometry_msgs/Vector3
+float64 x
+float64 y
+float64 z
+
+================================================================================
+MSG: geometry_msgs/Quaternion
+float64 x
+float64 y
+float64 z
+float64 w
+";
+
+/// Definition of the service of the buffer server.
+const LOOKUP_TRANSFORM_DEFINITION: &str = "string target_frame
+string source_frame
+time source_time
+duration timeout
+time target_time
+string fixed_frame
+bool advanced
+---
+geometry_msgs/TransformStamped transform
+tf2_msgs/TF2Error error
+uint8 tf_error
+";
+
+/// Implements the ROS1 wire format of a message made of the given fields, in order.
+macro_rules! impl_ros_msg {
+    ($message: ident { $($field: ident),* }) => {
+        impl RosMsg for $message {
+            fn encode<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+                $(self.$field.encode(w.by_ref())?;)*
+                Ok(())
+            }
+
+            fn decode<R: io::Read>(mut r: R) -> io::Result<Self> {
+                Ok($message {
+                    $($field: RosMsg::decode(r.by_ref())?,)*
+                })
+            }
+        }
+    };
+}
+
+impl_ros_msg!(Vector3 { x, y, z });
+impl_ros_msg!(Quaternion { x, y, z, w });
+impl_ros_msg!(Transform { translation, rotation });
+impl_ros_msg!(Header { seq, stamp, frame_id });
+impl_ros_msg!(TransformStamped { header, child_frame_id, transform });
+impl_ros_msg!(TF2Error { error, error_string });
+impl_ros_msg!(LookupTransformRequest { target_frame, source_frame, source_time, timeout, target_time, fixed_frame, advanced });
+impl_ros_msg!(LookupTransformResponse { transform, error, tf_error });
+
+impl RosMsg for TFMessage {
+    fn encode<W: io::Write>(&self, w: W) -> io::Result<()> {
+        encode_variable_slice(&self.transforms, w)
+    }
+
+    fn decode<R: io::Read>(r: R) -> io::Result<Self> {
+        Ok(TFMessage{transforms: decode_variable_vec(r)?})
+    }
+}
+
+impl rosrust::Message for TFMessage {
+    fn msg_definition() -> String {
+        TF_MESSAGE_DEFINITION.to_string()
+    }
+
+    fn md5sum() -> String {
+        "94810edda583a504dfda3829e70d7eec".to_string()
+    }
+
+    fn msg_type() -> String {
+        "tf2_msgs/TFMessage".to_string()
+    }
+}
+
+/// The service of the buffer server, the request and response are the goal and result of the
+/// tf2_msgs/LookupTransform action of tf2_ros.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LookupTransform;
+
+impl RosMsg for LookupTransform {
+    fn encode<W: io::Write>(&self, _w: W) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn decode<R: io::Read>(_r: R) -> io::Result<Self> {
+        Ok(LookupTransform)
+    }
+}
+
+impl rosrust::Message for LookupTransform {
+    fn msg_definition() -> String {
+        LOOKUP_TRANSFORM_DEFINITION.to_string()
+    }
+
+    fn md5sum() -> String {
+        "47f9b25b4c83c2af17f7404f9f26ca8e".to_string()
+    }
+
+    fn msg_type() -> String {
+        "rosrust_tf2/LookupTransform".to_string()
+    }
+}
+
+impl rosrust::ServicePair for LookupTransform {
+    type Request = LookupTransformRequest;
+    type Response = LookupTransformResponse;
+}
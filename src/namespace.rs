@@ -0,0 +1,123 @@
+use crate::time::Time;
+use crate::time::Duration;
+
+use crate::buffer::TfBuffer;
+use crate::core::{
+    TransformInterface,
+    TfError
+};
+use crate::msg;
+
+
+/// A view over a `TfBuffer` that resolves frame ids against a `tf_prefix`.
+///
+/// Unqualified frame ids such as `base_link` are resolved to `<prefix>/base_link`. Ids that
+/// already carry the prefix are left untouched and ids with a leading slash are global frames
+/// shared by the whole fleet, `/map` resolves to `map`. This lets code written for a single robot
+/// run unchanged against a buffer holding the frames of many robots. With the `ros1` feature,
+/// `TfBroadcaster::with_prefix` resolves the frame ids it publishes the same way.
+#[derive(Clone, Debug)]
+pub struct NamespacedTfBuffer<'a> {
+    buffer: &'a TfBuffer,
+    prefix: String
+}
+
+impl<'a> NamespacedTfBuffer<'a> {
+    pub fn new(buffer: &'a TfBuffer, prefix: &str) -> Self {
+        NamespacedTfBuffer{buffer, prefix: prefix.trim_matches('/').to_string()}
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Resolves a frame id against the prefix of this view.
+    pub fn resolve(&self, frame_id: &str) -> String {
+        resolve(&self.prefix, frame_id)
+    }
+
+    /// Qualifies the frame ids of an outgoing message so it can be broadcast next to the frames of
+    /// other robots.
+    pub fn qualify_message(&self, transforms: msg::TFMessage) -> msg::TFMessage {
+        qualify_message(&self.prefix, transforms)
+    }
+
+    /// Lists the frames of the underlying buffer that belong to this namespace.
+    pub fn frames(&self) -> Vec<String> {
+        self.buffer.frames()
+            .into_iter()
+            .filter(|frame| is_qualified(&self.prefix, frame))
+            .collect()
+    }
+}
+
+/// Resolves a frame id against `prefix`, given without leading or trailing slashes.
+pub(crate) fn resolve(prefix: &str, frame_id: &str) -> String {
+    if let Some(global) = frame_id.strip_prefix('/') {
+        return global.to_string();
+    }
+    if is_qualified(prefix, frame_id) {
+        return frame_id.to_string();
+    }
+    format!("{}/{}", prefix, frame_id)
+}
+
+/// Resolves the frame ids of every transform of a message against `prefix`.
+pub(crate) fn qualify_message(prefix: &str, mut transforms: msg::TFMessage) -> msg::TFMessage {
+    for transform in transforms.transforms.iter_mut() {
+        transform.header.frame_id = resolve(prefix, &transform.header.frame_id);
+        transform.child_frame_id = resolve(prefix, &transform.child_frame_id);
+    }
+    transforms
+}
+
+fn is_qualified(prefix: &str, frame_id: &str) -> bool {
+    prefix.is_empty() || frame_id.strip_prefix(prefix)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl<'a> TransformInterface for NamespacedTfBuffer<'a> {
+    fn lookup_transform(&self, target_frame: &str, source_frame: &str, time: Time) -> Result<msg::TransformStamped, TfError> {
+        self.buffer.lookup_transform(&self.resolve(target_frame), &self.resolve(source_frame), time)
+    }
+
+    fn can_transform(&self, target_frame: &str, source_frame: &str, time: Time, timeout: Duration) -> Result<bool, TfError> {
+        self.buffer.can_transform(&self.resolve(target_frame), &self.resolve(source_frame), time, timeout)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_transform;
+
+    /// Tests that two robots publishing the same unqualified frames can be looked up independently
+    #[test]
+    fn test_namespaced_lookup() {
+        let mut buffer = TfBuffer::new();
+        let robot_frames = |x| msg::TFMessage{transforms: vec!(
+            make_transform("/map", "base_link", 0, x, 0f64),
+            make_transform("base_link", "camera", 0, 0.5f64, 0f64)
+        )};
+
+        let robot1 = NamespacedTfBuffer::new(&buffer, "robot1");
+        let robot2 = NamespacedTfBuffer::new(&buffer, "/robot2/");
+        let message1 = robot1.qualify_message(robot_frames(1f64));
+        let message2 = robot2.qualify_message(robot_frames(2f64));
+        assert_eq!(message1.transforms[0].header.frame_id, "map");
+        assert_eq!(message1.transforms[0].child_frame_id, "robot1/base_link");
+        buffer.handle_incoming_transforms(message1, true, "test").unwrap();
+        buffer.handle_incoming_transforms(message2, true, "test").unwrap();
+
+        let time = Time{sec: 0, nsec: 0};
+        let robot1 = NamespacedTfBuffer::new(&buffer, "robot1");
+        let robot2 = NamespacedTfBuffer::new(&buffer, "robot2");
+        let tf = robot1.lookup_transform("/map", "camera", time).unwrap();
+        assert_eq!(tf.child_frame_id, "robot1/camera");
+        assert_eq!(tf.transform.translation.x, 1.5f64);
+        let tf = robot2.lookup_transform("/map", "robot2/camera", time).unwrap();
+        assert_eq!(tf.transform.translation.x, 2.5f64);
+
+        assert_eq!(robot1.frames(), vec!("robot1/base_link", "robot1/camera"));
+    }
+}
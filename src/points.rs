@@ -0,0 +1,144 @@
+use nalgebra::geometry;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::buffer::TfBuffer;
+use crate::core::TfError;
+use crate::msg;
+
+
+/// Applies `isometry` to every point of `points` in place.
+pub fn transform_points(isometry: &geometry::Isometry3<f64>, points: &mut [geometry::Point3<f64>]) {
+    #[cfg(feature = "rayon")]
+    let iter = points.par_iter_mut();
+    #[cfg(not(feature = "rayon"))]
+    let iter = points.iter_mut();
+
+    iter.for_each(|point| *point = isometry * *point);
+}
+
+/// Applies `isometry` in place to a flat buffer of points.
+///
+/// Each point spans `stride` floats and starts with its x, y and z coordinates, the remaining
+/// fields such as intensity are left untouched. A trailing point shorter than three floats is
+/// ignored. Points are transformed in double precision and only the results are rounded to
+/// floats, so that large translations do not swallow the coordinates.
+///
+/// # Panics
+///
+/// Panics if `stride` is smaller than 3.
+pub fn transform_xyz_buffer(isometry: &geometry::Isometry3<f64>, data: &mut [f32], stride: usize) {
+    assert!(stride >= 3, "a point needs at least 3 floats, got a stride of {}", stride);
+
+    #[cfg(feature = "rayon")]
+    let iter = data.par_chunks_mut(stride);
+    #[cfg(not(feature = "rayon"))]
+    let iter = data.chunks_mut(stride);
+
+    iter.filter(|point| point.len() >= 3).for_each(|point| {
+        let transformed = isometry * geometry::Point3::new(f64::from(point[0]), f64::from(point[1]), f64::from(point[2]));
+        for (value, transformed) in point.iter_mut().zip(transformed.coords.iter()) {
+            *value = *transformed as f32;
+        }
+    });
+}
+
+impl TfBuffer {
+    /// Moves points stamped with `header` into `target_frame` with a single lookup.
+    ///
+    /// Returns the header of the transformed points.
+    pub fn transform_points(&self, target_frame: &str, header: &msg::Header, points: &mut [geometry::Point3<f64>]) -> Result<msg::Header, TfError> {
+        let isometry = self.lookup_isometry(target_frame, &header.frame_id, header.stamp)?;
+        transform_points(&isometry, points);
+        Ok(transformed_header(target_frame, header))
+    }
+
+    /// Moves a flat buffer of points stamped with `header` into `target_frame` with a single
+    /// lookup, see `transform_xyz_buffer` for the layout of `data`.
+    ///
+    /// Returns the header of the transformed points.
+    pub fn transform_xyz_buffer(&self, target_frame: &str, header: &msg::Header, data: &mut [f32], stride: usize) -> Result<msg::Header, TfError> {
+        let isometry = self.lookup_isometry(target_frame, &header.frame_id, header.stamp)?;
+        transform_xyz_buffer(&isometry, data, stride);
+        Ok(transformed_header(target_frame, header))
+    }
+}
+
+fn transformed_header(target_frame: &str, header: &msg::Header) -> msg::Header {
+    msg::Header {
+        frame_id: target_frame.strip_prefix('/').unwrap_or(target_frame).to_string(),
+        stamp: header.stamp,
+        seq: header.seq
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::time::Time;
+
+    fn build_buffer() -> TfBuffer {
+        let mut buffer = TfBuffer::new();
+        let base_link_to_laser = msg::TransformStamped {
+            child_frame_id: "laser".to_string(),
+            header: msg::Header {
+                frame_id: "base_link".to_string(),
+                stamp: Time{sec: 0, nsec: 0},
+                seq: 1
+            },
+            transform: msg::Transform{
+                rotation: msg::Quaternion{
+                    x: 0f64, y: 0f64, z: 0.5f64.sqrt(), w: 0.5f64.sqrt()
+                },
+                translation: msg::Vector3{
+                    x: 1f64, y: 0f64, z: 0.5f64
+                }
+            }
+        };
+        buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(base_link_to_laser)}, true, "test").unwrap();
+        buffer
+    }
+
+    fn laser_header() -> msg::Header {
+        msg::Header {
+            frame_id: "laser".to_string(),
+            stamp: Time{sec: 0, nsec: 0},
+            seq: 7
+        }
+    }
+
+    /// Tests that points are rotated then translated into the target frame
+    #[test]
+    fn test_transform_points() {
+        let buffer = build_buffer();
+        let mut points = vec!(geometry::Point3::new(1f64, 0f64, 0f64), geometry::Point3::new(0f64, 2f64, 1f64));
+        let header = buffer.transform_points("base_link", &laser_header(), &mut points).unwrap();
+        assert_eq!(header.frame_id, "base_link");
+        assert_eq!(header.seq, 7);
+        assert!((points[0] - geometry::Point3::new(1f64, 1f64, 0.5f64)).norm() < 1e-9);
+        assert!((points[1] - geometry::Point3::new(-1f64, 0f64, 1.5f64)).norm() < 1e-9);
+    }
+
+    /// Tests that only the coordinates of a strided buffer are transformed
+    #[test]
+    fn test_transform_xyz_buffer() {
+        let buffer = build_buffer();
+        let mut data = vec!(1f32, 0f32, 0f32, 42f32, 0f32, 2f32, 1f32, 43f32);
+        buffer.transform_xyz_buffer("base_link", &laser_header(), &mut data, 4).unwrap();
+        let expected = [1f32, 1f32, 0.5f32, 42f32, -1f32, 0f32, 1.5f32, 43f32];
+        for (value, expected) in data.iter().zip(expected.iter()) {
+            assert!((value - expected).abs() < 1e-6);
+        }
+    }
+
+    /// Tests that a large translation is applied in double precision before rounding to floats
+    #[test]
+    fn test_transform_xyz_buffer_large_translation() {
+        let isometry = geometry::Isometry3::translation(-1_000_000.123456f64, 0f64, 4_000_000.5f64);
+        let mut data = vec!(1_000_000f32, 1f32, -4_000_000f32);
+        transform_xyz_buffer(&isometry, &mut data, 3);
+        assert!((data[0] - -0.123456f32).abs() < 1e-6);
+        assert_eq!(data[1], 1f32);
+        assert_eq!(data[2], 0.5f32);
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt::Write;
+
+use crate::buffer::TfBuffer;
+use crate::core::TfError;
+use crate::msg;
+use crate::time::Time;
+
+
+const SNAPSHOT_HEADER: &str = "# rosrust_tf2 snapshot v1";
+const SNAPSHOT_COLUMNS: &str = "# kind,parent,child,authority,stamp_nanos,tx,ty,tz,qx,qy,qz,qw";
+
+/// Percent-encodes the characters that would break the line and field structure of a snapshot.
+fn escape_field(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '%' | ',' | '\n' | '\r' => write!(escaped, "%{:02X}", c as u32).unwrap(),
+            _ => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+fn unescape_field(field: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            let code: String = chars.by_ref().take(2).collect();
+            if code.len() != 2 || !code.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            let code = u8::from_str_radix(&code, 16).ok()?;
+            unescaped.push(char::from(code));
+        } else {
+            unescaped.push(c);
+        }
+    }
+    Some(unescaped)
+}
+
+impl TfBuffer {
+    /// Writes the edges of the tree as text, one transform per line, the whole history of dynamic
+    /// edges included. `from_snapshot` reads it back.
+    ///
+    /// Lines are made of comma separated fields: `static` or `dynamic`, the parent and child
+    /// frames, the publishing node, the stamp in nanoseconds and the translation and rotation.
+    /// Commas, percent signs and line breaks in frame ids and node names are percent-encoded.
+    /// Floats are written with as many digits as needed to be read back exactly.
+    pub fn to_snapshot(&self) -> String {
+        let mut snapshot = format!("{}\n{}\n", SNAPSHOT_HEADER, SNAPSHOT_COLUMNS);
+        for edge in self.tree_edges() {
+            let kind = if edge.static_tf { "static" } else { "dynamic" };
+            for transform in edge.transforms {
+                let msg::Transform{translation, rotation} = &transform.transform;
+                writeln!(snapshot, "{},{},{},{},{},{},{},{},{},{},{},{}", kind, escape_field(edge.parent_frame),
+                    escape_field(edge.child_frame), escape_field(edge.authority), transform.header.stamp.nanos(), translation.x, translation.y, translation.z,
+                    rotation.x, rotation.y, rotation.z, rotation.w).unwrap();
+            }
+        }
+        snapshot
+    }
+
+    /// Builds a buffer from the output of `to_snapshot`. Lines starting with `#` are skipped.
+    pub fn from_snapshot(snapshot: &str) -> Result<TfBuffer, TfError> {
+        let mut buffer = TfBuffer::new();
+        for (index, line) in snapshot.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid_line = |reason: &str| TfError::InvalidArgument(format!("snapshot line {}: {}", index + 1, reason));
+            let fields: Vec<&str> = line.split(',').collect();
+            if fields.len() != 12 {
+                return Err(invalid_line("expected 12 fields"));
+            }
+            let static_tf = match fields[0] {
+                "static" => true,
+                "dynamic" => false,
+                _ => return Err(invalid_line("expected static or dynamic"))
+            };
+            let mut names = Vec::with_capacity(3);
+            for field in &fields[1..4] {
+                names.push(unescape_field(field).ok_or_else(|| invalid_line("invalid escape sequence"))?);
+            }
+            let stamp: i64 = fields[4].parse().map_err(|_| invalid_line("invalid stamp"))?;
+            let mut values = [0f64; 7];
+            for (value, field) in values.iter_mut().zip(&fields[5..]) {
+                *value = field.parse().map_err(|_| invalid_line("invalid transform value"))?;
+            }
+            let transform = msg::TransformStamped {
+                child_frame_id: names[1].clone(),
+                header: msg::Header {
+                    frame_id: names[0].clone(),
+                    stamp: Time::from_nanos(stamp),
+                    seq: 0
+                },
+                transform: msg::Transform {
+                    translation: msg::Vector3{x: values[0], y: values[1], z: values[2]},
+                    rotation: msg::Quaternion{x: values[3], y: values[4], z: values[5], w: values[6]}
+                }
+            };
+            buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(transform)}, static_tf, &names[2])
+                .map_err(|err| invalid_line(&format!("{:?}", err)))?;
+        }
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Tests that a buffer is rebuilt identically from its snapshot
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut buffer = TfBuffer::new();
+        let mut transform = msg::TransformStamped {
+            child_frame_id: "camera".to_string(),
+            header: msg::Header {
+                frame_id: "base_link".to_string(),
+                stamp: Time{sec: 0, nsec: 0},
+                seq: 0
+            },
+            transform: msg::Transform::from_xyz_rpy(0.1, 0.2, 0.3, 0.4, 0.5, 0.6)
+        };
+        buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(transform.clone())}, true, "/rsp").unwrap();
+        transform.header.frame_id = "odom".to_string();
+        transform.child_frame_id = "base_link".to_string();
+        for sec in 1..4 {
+            transform.header.stamp = Time{sec, nsec: 1};
+            transform.transform.translation.x = sec as f64 / 3f64;
+            buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(transform.clone())}, false, "/odom").unwrap();
+        }
+
+        let snapshot = buffer.to_snapshot();
+        assert_eq!(snapshot.lines().count(), 6);
+        let restored = TfBuffer::from_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.to_snapshot(), snapshot);
+        assert_eq!(restored.all_frames_as_yaml(), buffer.all_frames_as_yaml());
+
+        assert!(matches!(TfBuffer::from_snapshot("static,a,b,,0,0,0,0,0,0,0"), Err(TfError::InvalidArgument(_))));
+        assert!(matches!(TfBuffer::from_snapshot("static,a,b,,0,0,0,0,0,0,0,x"), Err(TfError::InvalidArgument(_))));
+    }
+
+    /// Tests that frame ids and node names holding separators survive a round trip
+    #[test]
+    fn test_snapshot_escaping() {
+        let mut buffer = TfBuffer::new();
+        let transform = msg::TransformStamped {
+            child_frame_id: "camera,left%2C".to_string(),
+            header: msg::Header {
+                frame_id: "base,link".to_string(),
+                stamp: Time{sec: 0, nsec: 0},
+                seq: 0
+            },
+            transform: msg::Transform::from_xyz_rpy(0.1, 0.2, 0.3, 0.4, 0.5, 0.6)
+        };
+        buffer.handle_incoming_transforms(msg::TFMessage{transforms: vec!(transform)}, true, "/rsp,1\n# 2").unwrap();
+
+        let snapshot = buffer.to_snapshot();
+        assert_eq!(snapshot.lines().count(), 3);
+        let restored = TfBuffer::from_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.frames(), vec!("base,link", "camera,left%2C"));
+        assert_eq!(restored.frame_authority("camera,left%2C"), Some("/rsp,1\n# 2"));
+        assert_eq!(restored.to_snapshot(), snapshot);
+
+        assert!(matches!(TfBuffer::from_snapshot("static,a%2,b,,0,0,0,0,0,0,0,1"), Err(TfError::InvalidArgument(_))));
+        assert!(matches!(TfBuffer::from_snapshot("static,a%zz,b,,0,0,0,0,0,0,0,1"), Err(TfError::InvalidArgument(_))));
+        assert!(matches!(TfBuffer::from_snapshot("static,a%+1,b,,0,0,0,0,0,0,0,1"), Err(TfError::InvalidArgument(_))));
+    }
+}
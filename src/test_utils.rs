@@ -0,0 +1,23 @@
+//! Fixtures shared by the test modules and the benches.
+use crate::msg;
+use crate::time::Time;
+
+
+/// Builds a transform from `parent` to `child` stamped at `sec`, translated by `x` along the x
+/// axis and turned by `yaw` around the z axis.
+pub fn make_transform(parent: &str, child: &str, sec: u32, x: f64, yaw: f64) -> msg::TransformStamped {
+    msg::TransformStamped {
+        child_frame_id: child.to_string(),
+        header: msg::Header {
+            frame_id: parent.to_string(),
+            stamp: Time{sec, nsec: 0},
+            seq: 0
+        },
+        transform: msg::Transform::from_xyz_rpy(x, 0f64, 0f64, 0f64, 0f64, yaw)
+    }
+}
+
+/// Builds a message holding the single transform `make_transform` builds.
+pub fn make_message(parent: &str, child: &str, sec: u32, x: f64, yaw: f64) -> msg::TFMessage {
+    msg::TFMessage{transforms: vec!(make_transform(parent, child, sec, x, yaw))}
+}
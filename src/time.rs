@@ -0,0 +1,311 @@
+//! Time and duration types of the buffer, with the layout and semantics of the ROS ones so the
+//! core of the crate does not depend on a ROS client library. Arithmetic saturates to the range
+//! of the result instead of wrapping.
+//!
+//! With the `ros1` feature, both convert to and from their `rosrust` counterparts.
+use std::cmp;
+use std::convert::TryInto;
+use std::fmt;
+use std::hash::{
+    Hash,
+    Hasher
+};
+use std::ops;
+use std::time;
+
+
+const BILLION: i64 = 1_000_000_000;
+
+/// A point in time, with nanosecond precision.
+#[derive(Clone, Copy, Debug, Default, Eq)]
+pub struct Time {
+    pub sec: u32,
+    /// Nanoseconds inside the current second.
+    pub nsec: u32
+}
+
+/// A signed span of time, with nanosecond precision.
+#[derive(Clone, Copy, Debug, Default, Eq)]
+pub struct Duration {
+    /// Negative for negative durations.
+    pub sec: i32,
+    /// Nanoseconds inside the current second, negative for negative durations.
+    pub nsec: i32
+}
+
+impl Time {
+    pub fn new() -> Time {
+        Self::default()
+    }
+
+    /// Builds a time from nanoseconds since 1970, saturating to the range of `Time`: negative
+    /// values give zero and values after 2106 give the last representable time.
+    pub fn from_nanos(nanos: i64) -> Time {
+        let nanos = nanos.clamp(0, i64::from(u32::MAX) * BILLION + BILLION - 1);
+        Time{sec: (nanos / BILLION) as u32, nsec: (nanos % BILLION) as u32}
+    }
+
+    pub fn from_seconds(sec: u32) -> Time {
+        Time{sec, nsec: 0}
+    }
+
+    pub fn nanos(self) -> i64 {
+        i64::from(self.sec) * BILLION + i64::from(self.nsec)
+    }
+
+    pub fn seconds(self) -> f64 {
+        f64::from(self.sec) + f64::from(self.nsec) / BILLION as f64
+    }
+}
+
+impl Duration {
+    pub fn new() -> Duration {
+        Self::default()
+    }
+
+    /// Builds a duration from nanoseconds, saturating to the range of `Duration`, about 68
+    /// years either way.
+    pub fn from_nanos(nanos: i64) -> Duration {
+        let nanos = nanos.clamp(i64::from(i32::MIN) * BILLION - BILLION + 1, i64::from(i32::MAX) * BILLION + BILLION - 1);
+        Duration{sec: (nanos / BILLION) as i32, nsec: (nanos % BILLION) as i32}
+    }
+
+    pub fn from_seconds(sec: i32) -> Duration {
+        Duration{sec, nsec: 0}
+    }
+
+    pub fn nanos(self) -> i64 {
+        i64::from(self.sec) * BILLION + i64::from(self.nsec)
+    }
+
+    pub fn seconds(self) -> f64 {
+        f64::from(self.sec) + f64::from(self.nsec) / BILLION as f64
+    }
+}
+
+/// Writes nanoseconds as seconds with a fixed number of decimals, as ROS does.
+fn fmt_nanos(nanos: i64, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let sign = if nanos < 0 { "-" } else { "" };
+    write!(f, "{}{}.{:09}", sign, nanos.abs() / BILLION, nanos.abs() % BILLION)
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_nanos(self.nanos(), f)
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_nanos(self.nanos(), f)
+    }
+}
+
+impl PartialEq for Time {
+    fn eq(&self, other: &Self) -> bool {
+        self.nanos() == other.nanos()
+    }
+}
+
+impl PartialOrd for Time {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Time {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.nanos().cmp(&other.nanos())
+    }
+}
+
+impl Hash for Time {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.nanos().hash(state)
+    }
+}
+
+impl PartialEq for Duration {
+    fn eq(&self, other: &Self) -> bool {
+        self.nanos() == other.nanos()
+    }
+}
+
+impl PartialOrd for Duration {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Duration {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.nanos().cmp(&other.nanos())
+    }
+}
+
+impl Hash for Duration {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.nanos().hash(state)
+    }
+}
+
+impl ops::Add<Duration> for Time {
+    type Output = Time;
+    fn add(self, rhs: Duration) -> Time {
+        Time::from_nanos(self.nanos() + rhs.nanos())
+    }
+}
+
+impl ops::Sub<Duration> for Time {
+    type Output = Time;
+    fn sub(self, rhs: Duration) -> Time {
+        Time::from_nanos(self.nanos() - rhs.nanos())
+    }
+}
+
+impl ops::Sub<Time> for Time {
+    type Output = Duration;
+    fn sub(self, rhs: Time) -> Duration {
+        Duration::from_nanos(self.nanos() - rhs.nanos())
+    }
+}
+
+impl ops::Add<Duration> for Duration {
+    type Output = Duration;
+    fn add(self, rhs: Duration) -> Duration {
+        Duration::from_nanos(self.nanos() + rhs.nanos())
+    }
+}
+
+impl ops::Sub<Duration> for Duration {
+    type Output = Duration;
+    fn sub(self, rhs: Duration) -> Duration {
+        Duration::from_nanos(self.nanos() - rhs.nanos())
+    }
+}
+
+impl ops::Neg for Duration {
+    type Output = Duration;
+    fn neg(self) -> Duration {
+        Duration::from_nanos(-self.nanos())
+    }
+}
+
+impl From<time::SystemTime> for Time {
+    /// Panics for times before 1970 or after 2106, which do not fit.
+    fn from(time: time::SystemTime) -> Self {
+        let elapsed = time.duration_since(time::SystemTime::UNIX_EPOCH)
+            .expect("times before 1970 do not fit in a Time");
+        Time {
+            sec: elapsed.as_secs().try_into().expect("times after 2106 do not fit in a Time"),
+            nsec: elapsed.subsec_nanos()
+        }
+    }
+}
+
+impl From<time::Duration> for Duration {
+    /// Panics for durations longer than 68 years, which do not fit.
+    fn from(duration: time::Duration) -> Self {
+        Duration {
+            sec: duration.as_secs().try_into().expect("durations longer than 68 years do not fit in a Duration"),
+            nsec: duration.subsec_nanos() as i32
+        }
+    }
+}
+
+impl From<Duration> for time::Duration {
+    /// Negative durations are clamped to zero.
+    fn from(duration: Duration) -> Self {
+        time::Duration::from_nanos(duration.nanos().max(0) as u64)
+    }
+}
+
+#[cfg(feature = "ros1")]
+impl From<rosrust::Time> for Time {
+    fn from(time: rosrust::Time) -> Self {
+        Time{sec: time.sec, nsec: time.nsec}
+    }
+}
+
+#[cfg(feature = "ros1")]
+impl From<Time> for rosrust::Time {
+    fn from(time: Time) -> Self {
+        rosrust::Time{sec: time.sec, nsec: time.nsec}
+    }
+}
+
+#[cfg(feature = "ros1")]
+impl From<rosrust::Duration> for Duration {
+    fn from(duration: rosrust::Duration) -> Self {
+        Duration{sec: duration.sec, nsec: duration.nsec}
+    }
+}
+
+#[cfg(feature = "ros1")]
+impl From<Duration> for rosrust::Duration {
+    fn from(duration: Duration) -> Self {
+        rosrust::Duration{sec: duration.sec, nsec: duration.nsec}
+    }
+}
+
+#[cfg(feature = "ros1")]
+impl rosrust::RosMsg for Time {
+    fn encode<W: std::io::Write>(&self, w: W) -> std::io::Result<()> {
+        rosrust::Time::from(*self).encode(w)
+    }
+
+    fn decode<R: std::io::Read>(r: R) -> std::io::Result<Self> {
+        rosrust::Time::decode(r).map(Time::from)
+    }
+}
+
+#[cfg(feature = "ros1")]
+impl rosrust::RosMsg for Duration {
+    fn encode<W: std::io::Write>(&self, w: W) -> std::io::Result<()> {
+        rosrust::Duration::from(*self).encode(w)
+    }
+
+    fn decode<R: std::io::Read>(r: R) -> std::io::Result<Self> {
+        rosrust::Duration::decode(r).map(Duration::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Tests the arithmetic and formatting of times and durations, negative ones included
+    #[test]
+    fn test_time_arithmetic() {
+        let time = Time{sec: 12, nsec: 500_000_000};
+        assert_eq!(time.nanos(), 12_500_000_000);
+        assert_eq!(Time::from_nanos(time.nanos()), time);
+        assert_eq!(time + Duration::from_nanos(-1_000_000_000), Time{sec: 11, nsec: 500_000_000});
+        assert_eq!(time - Time{sec: 13, nsec: 0}, Duration{sec: 0, nsec: -500_000_000});
+        assert_eq!(-Duration::from_seconds(2), Duration::from_nanos(-2_000_000_000));
+        assert!(Duration::from_nanos(-1) < Duration::default());
+        assert_eq!(format!("{}", time), "12.500000000");
+        assert_eq!(format!("{}", Duration::from_nanos(-1)), "-0.000000001");
+        assert_eq!(time::Duration::from(Duration::from_nanos(-5)), time::Duration::from_nanos(0));
+        assert_eq!(Duration::from(time::Duration::from_millis(1500)), Duration{sec: 1, nsec: 500_000_000});
+    }
+
+    /// Tests that times and durations out of range saturate instead of wrapping
+    #[test]
+    fn test_saturation() {
+        assert_eq!(Time::from_nanos(-1), Time{sec: 0, nsec: 0});
+        assert_eq!(Time::from_nanos(i64::MIN), Time{sec: 0, nsec: 0});
+        assert_eq!(Time::from_nanos(i64::MAX), Time{sec: u32::MAX, nsec: 999_999_999});
+        assert_eq!(Time{sec: 1, nsec: 0} - Duration::from_seconds(2), Time{sec: 0, nsec: 0});
+
+        let longest = Duration{sec: i32::MAX, nsec: 999_999_999};
+        let most_negative = Duration{sec: i32::MIN, nsec: -999_999_999};
+        assert_eq!(Duration::from_nanos(-1_500_000_000), Duration{sec: -1, nsec: -500_000_000});
+        assert_eq!(Duration::from_nanos(i64::MAX), longest);
+        assert_eq!(Duration::from_nanos(i64::MIN), most_negative);
+        assert_eq!(Duration::from_nanos(100 * 365 * 24 * 3600 * BILLION), longest);
+        assert_eq!(longest + Duration::from_seconds(1), longest);
+        assert_eq!(-most_negative, longest);
+        assert_eq!(Time{sec: u32::MAX, nsec: 0} - Time{sec: 0, nsec: 0}, longest);
+    }
+}
@@ -1,63 +1,23 @@
+use nalgebra::base::{
+    Matrix3,
+    Vector3,
+    Vector6
+};
 use nalgebra::geometry;
-use nalgebra::base;
 
 use crate::msg;
 
 
-// TODO find a better localization for this helper method
-fn from_na_vector_to_vector_msg(vector: base::Vector3<f64>) -> msg::Vector3
-{
-    msg::Vector3 {
-        x: vector[0],
-        y: vector[1],
-        z: vector[2]
-    }
-}
-
-// TODO find a better localization for this helper method
-fn from_vector_msg_to_na_vector(vector: msg::Vector3) -> base::Vector3<f64>
-{
-    let msg::Vector3{x, y, z} = vector;
-    base::Vector3::new(x, y, z)
-}
-
-// TODO find a better localization for this helper method
-fn from_quaternion_msg_to_na_quarternion(quaternion: msg::Quaternion) -> geometry::UnitQuaternion<f64>
-{
-    let msg::Quaternion {x, y, z, w} = quaternion;
-    geometry::UnitQuaternion::new_normalize(
-        geometry::Quaternion::new(w, x, y, z)
-    )
-}
-
-
-// TODO find a better localization for this helper method
-fn from_na_quaternion_to_quaternion_msg(quaternion: geometry::Quaternion<f64>) -> msg::Quaternion
-{
-    msg::Quaternion {
-        x: quaternion.coords[0],
-        y: quaternion.coords[1],
-        z: quaternion.coords[2],
-        w: quaternion.coords[3]
-    }
-}
-
 ///Converts a transform from xyz translation + quaternion format to an SE3 matrix
 pub fn isometry_from_transform_msg(transform: msg::Transform) -> geometry::Isometry3<f64>
 {
-    let qt = from_quaternion_msg_to_na_quarternion(transform.rotation);
-    let trans = from_vector_msg_to_na_vector(transform.translation);
-
-    geometry::Isometry3::new(trans, qt.scaled_axis())
+    transform.into()
 }
 
 ///Converts an SE3 matrix to a Transform
 pub fn transform_msg_from_isometry(isometry: geometry::Isometry3<f64>) -> msg::Transform
 {
-    msg::Transform {
-        translation: from_na_vector_to_vector_msg(isometry.translation.vector),
-        rotation: from_na_quaternion_to_quaternion_msg(*isometry.rotation)
-    }
+    isometry.into()
 }
 
 ///Get the inverse transform
@@ -84,28 +44,112 @@ pub fn chain_transforms(transforms: Vec<msg::Transform>) -> msg::Transform {
     }
 }
 
-pub fn interpolate(t1: msg::Transform, t2: msg::Transform, weight: f64) -> msg::Transform {
+///Interpolates between two transforms. `ratio` is the position between them, 0 gives `t1` and 1 gives `t2`.
+///The translation is interpolated linearly and the rotation along the shortest arc, as tf2 does.
+pub fn interpolate(t1: msg::Transform, t2: msg::Transform, ratio: f64) -> msg::Transform {
     let iso1 = isometry_from_transform_msg(t1);
     let iso2 = isometry_from_transform_msg(t2);
-    let res  = iso1.try_lerp_slerp(&iso2, 1f64 - weight, 1e-9);
-    match res {
-        Some(iso) => transform_msg_from_isometry(iso),
-        None => {
-            let translation_lerp = iso1.translation.vector.lerp(&iso2.translation.vector, 1f64 - weight);
-            if weight > 0.5 {
-                msg::Transform{
-                    translation: from_na_vector_to_vector_msg(translation_lerp),
-                    rotation: from_na_quaternion_to_quaternion_msg(*iso1.rotation)
-                }
-            }
-            else {
-                msg::Transform{
-                    translation: from_na_vector_to_vector_msg(translation_lerp),
-                    rotation: from_na_quaternion_to_quaternion_msg(*iso2.rotation)
-                }
-            }
-        } 
+    transform_msg_from_isometry(interpolate_isometry(&iso1, &iso2, ratio))
+}
+
+///Same as `interpolate` but works directly on SE3 matrices
+pub fn interpolate_isometry(iso1: &geometry::Isometry3<f64>, iso2: &geometry::Isometry3<f64>, ratio: f64) -> geometry::Isometry3<f64> {
+    let translation = iso1.translation.vector.lerp(&iso2.translation.vector, ratio);
+    // Slerp is undefined between nearly equal rotations, a normalized lerp is then exact enough
+    let rotation = iso1.rotation
+        .try_slerp(&iso2.rotation, ratio, 1e-9)
+        .unwrap_or_else(|| iso1.rotation.nlerp(&iso2.rotation, ratio));
+    geometry::Isometry3::from_parts(translation.into(), rotation)
+}
+
+/// Below this rotation angle the SE3 maps switch to their Taylor expansions.
+const SMALL_ANGLE: f64 = 1e-4;
+/// Convergence criteria of the Karcher mean used by `weighted_mean`.
+const MAX_MEAN_ITERATIONS: usize = 100;
+const MEAN_TOLERANCE: f64 = 1e-12;
+
+///Maps a tangent vector ordered as (linear, angular) to an SE3 matrix
+pub fn se3_exp(tangent: &Vector6<f64>) -> geometry::Isometry3<f64> {
+    let rho: Vector3<f64> = tangent.fixed_rows::<3>(0).into();
+    let omega: Vector3<f64> = tangent.fixed_rows::<3>(3).into();
+    let theta = omega.norm();
+    let omega_hat = omega.cross_matrix();
+    let (a, b) = if theta < SMALL_ANGLE {
+        (0.5 - theta * theta / 24.0, 1.0 / 6.0 - theta * theta / 120.0)
+    }
+    else {
+        ((1.0 - theta.cos()) / (theta * theta), (theta - theta.sin()) / (theta * theta * theta))
+    };
+    let v = Matrix3::identity() + omega_hat * a + omega_hat * omega_hat * b;
+    geometry::Isometry3::from_parts(
+        (v * rho).into(),
+        geometry::UnitQuaternion::from_scaled_axis(omega)
+    )
+}
+
+///Maps an SE3 matrix to its tangent vector ordered as (linear, angular), the inverse of `se3_exp`.
+///The rotation part has a norm of at most pi.
+pub fn se3_log(isometry: &geometry::Isometry3<f64>) -> Vector6<f64> {
+    let omega = isometry.rotation.scaled_axis();
+    let theta = omega.norm();
+    let omega_hat = omega.cross_matrix();
+    let c = if theta < SMALL_ANGLE {
+        1.0 / 12.0 + theta * theta / 720.0
+    }
+    else {
+        (1.0 - theta * theta.sin() / (2.0 * (1.0 - theta.cos()))) / (theta * theta)
+    };
+    let v_inv = Matrix3::identity() - omega_hat * 0.5 + omega_hat * omega_hat * c;
+    let rho = v_inv * isometry.translation.vector;
+    Vector6::new(rho[0], rho[1], rho[2], omega[0], omega[1], omega[2])
+}
+
+///Geodesic distance between two transforms, returned as the translation distance and the angle
+///of the rotation between them
+pub fn distance(t1: &msg::Transform, t2: &msg::Transform) -> (f64, f64) {
+    let iso1 = isometry_from_transform_msg(t1.clone());
+    let iso2 = isometry_from_transform_msg(t2.clone());
+    let translation = (iso1.translation.vector - iso2.translation.vector).norm();
+    let angle = rotation_angle(&(iso1.rotation.inverse() * iso2.rotation));
+    (translation, angle)
+}
+
+/// Angle of a rotation in [0, pi]. Unlike `UnitQuaternion::angle` it keeps full precision near
+/// the identity.
+fn rotation_angle(rotation: &geometry::UnitQuaternion<f64>) -> f64 {
+    2.0 * rotation.imag().norm().atan2(rotation.scalar().abs())
+}
+
+///Weighted mean of transforms. The translations are averaged linearly and the rotations with the
+///Karcher mean on SO3, the rotation minimizing the weighted sum of squared angles to the inputs.
+///Returns `None` when there is nothing to average.
+pub fn weighted_mean(transforms: &[(msg::Transform, f64)]) -> Option<msg::Transform> {
+    let total_weight: f64 = transforms.iter().map(|(_, weight)| weight).sum();
+    if transforms.is_empty() || total_weight <= 0.0 {
+        return None;
+    }
+    let isometries: Vec<(geometry::Isometry3<f64>, f64)> = transforms
+        .iter()
+        .map(|(transform, weight)| (isometry_from_transform_msg(transform.clone()), weight / total_weight))
+        .collect();
+
+    let translation = isometries
+        .iter()
+        .fold(Vector3::zeros(), |sum, (isometry, weight)| sum + isometry.translation.vector * *weight);
+
+    let mut rotation = isometries[0].0.rotation;
+    for _ in 0..MAX_MEAN_ITERATIONS {
+        let step = isometries
+            .iter()
+            .fold(Vector3::zeros(), |sum, (isometry, weight)| {
+                sum + (rotation.inverse() * isometry.rotation).scaled_axis() * *weight
+            });
+        rotation *= geometry::UnitQuaternion::from_scaled_axis(step);
+        if step.norm() < MEAN_TOLERANCE {
+            break;
+        }
     }
+    Some(transform_msg_from_isometry(geometry::Isometry3::from_parts(translation.into(), rotation)))
 }
 
 #[cfg(test)]
@@ -143,4 +187,99 @@ mod test {
         };
         assert_eq!(interpolate(tf1, tf2, 0.5), expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_interpolation_ratio() {
+        let tf1 = msg::Transform::from_xyz_rpy(0f64, 0f64, 0f64, 0f64, 0f64, 0f64);
+        let tf2 = msg::Transform::from_xyz_rpy(4f64, 0f64, 0f64, 0f64, 0f64, 1f64);
+        let res = interpolate(tf1.clone(), tf2.clone(), 0.25);
+        assert!((res.translation.x - 1f64).abs() < 1e-12);
+        assert!((res.rotation.to_rpy().2 - 0.25f64).abs() < 1e-12);
+        let (translation, angle) = distance(&interpolate(tf1.clone(), tf2.clone(), 0f64), &tf1);
+        assert!(translation < 1e-12 && angle < 1e-12);
+
+        let same_rotation = msg::Transform::from_xyz_rpy(4f64, 0f64, 0f64, 0f64, 0f64, 0f64);
+        let res = interpolate(tf1, same_rotation, 0.75);
+        assert!((res.translation.x - 3f64).abs() < 1e-12);
+        assert_eq!(res.rotation, msg::Quaternion{x: 0f64, y: 0f64, z: 0f64, w: 1f64});
+    }
+
+    #[test]
+    fn test_isometry_round_trip() {
+        let tf = msg::Transform {
+            translation: msg::Vector3{x: 1f64, y: -2f64, z: 3f64},
+            rotation: msg::Quaternion{x: 0f64, y: 0f64, z: 0.5f64.sqrt(), w: 0.5f64.sqrt()}
+        };
+        let isometry: geometry::Isometry3<f64> = tf.clone().into();
+        assert_eq!(isometry.translation.vector, nalgebra::Vector3::new(1f64, -2f64, 3f64));
+        assert!((isometry.rotation.angle() - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert_eq!(msg::Transform::from(isometry), tf);
+    }
+
+    fn assert_isometry_eq(iso1: &geometry::Isometry3<f64>, iso2: &geometry::Isometry3<f64>, tolerance: f64) {
+        assert!((iso1.translation.vector - iso2.translation.vector).norm() < tolerance, "{} != {}", iso1, iso2);
+        assert!(rotation_angle(&(iso1.rotation.inverse() * iso2.rotation)) < tolerance, "{} != {}", iso1, iso2);
+    }
+
+    #[test]
+    fn test_se3_round_trip_near_identity() {
+        for scale in [0f64, 1e-12, 1e-7, 1e-5, 1e-3] {
+            let tangent = Vector6::new(1f64, -2f64, 0.5f64, 0.3f64, -0.2f64, 0.6f64) * scale;
+            let isometry = se3_exp(&tangent);
+            assert!((se3_log(&isometry) - tangent).norm() < 1e-15 + scale * 1e-9);
+            assert_isometry_eq(&se3_exp(&se3_log(&isometry)), &isometry, 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_se3_round_trip_near_pi() {
+        let axis = Vector3::new(1f64, 2f64, -2f64).normalize();
+        for angle in [std::f64::consts::PI - 1e-3, std::f64::consts::PI - 1e-7, std::f64::consts::PI] {
+            let omega = axis * angle;
+            let tangent = Vector6::new(0.4f64, -1f64, 2f64, omega[0], omega[1], omega[2]);
+            let isometry = se3_exp(&tangent);
+            assert_isometry_eq(&se3_exp(&se3_log(&isometry)), &isometry, 1e-9);
+            if angle < std::f64::consts::PI {
+                assert!((se3_log(&isometry) - tangent).norm() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_se3_exp_screw_motion() {
+        // A quarter turn about z while moving along y ends up on the unit circle
+        let tangent = Vector6::new(0f64, std::f64::consts::FRAC_PI_2, 0f64, 0f64, 0f64, std::f64::consts::FRAC_PI_2);
+        let isometry = se3_exp(&tangent);
+        assert!((isometry.translation.vector - Vector3::new(-1f64, 1f64, 0f64)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_distance() {
+        let tf1 = msg::Transform {
+            translation: msg::Vector3{x: 1f64, y: 1f64, z: 0f64},
+            rotation: msg::Quaternion{x: 0f64, y: 0f64, z: 0f64, w: 1f64}
+        };
+        let tf2 = msg::Transform {
+            translation: msg::Vector3{x: 4f64, y: 5f64, z: 0f64},
+            rotation: msg::Quaternion{x: 0f64, y: 0f64, z: 0.5f64.sqrt(), w: -(0.5f64.sqrt())}
+        };
+        let (translation, angle) = distance(&tf1, &tf2);
+        assert!((translation - 5f64).abs() < 1e-12);
+        assert!((angle - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_weighted_mean() {
+        let yaw = |angle: f64, x: f64| transform_msg_from_isometry(geometry::Isometry3::new(
+            Vector3::new(x, 0f64, 0f64), Vector3::new(0f64, 0f64, angle)));
+        let mean = weighted_mean(&[(yaw(0.1f64, 1f64), 1f64), (yaw(0.4f64, 4f64), 2f64)]).unwrap();
+        let (translation, angle) = distance(&mean, &yaw(0.3f64, 3f64));
+        assert!(translation < 1e-12);
+        assert!(angle < 1e-12);
+
+        let mean = weighted_mean(&[(yaw(3.1f64, 0f64), 1f64), (yaw(-3.1f64, 0f64), 1f64)]).unwrap();
+        assert!(distance(&mean, &yaw(std::f64::consts::PI, 0f64)).1 < 1e-9);
+
+        assert!(weighted_mean(&[]).is_none());
+    }
+}
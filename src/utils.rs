@@ -1,5 +1,10 @@
+use crate::core::TfError;
 use crate::transforms;
 use crate::msg;
+use crate::time::{
+    Duration,
+    Time
+};
 
 
 /// Calculates the inverse of a ros transform
@@ -8,7 +13,7 @@ pub fn get_inverse(transform: msg::TransformStamped) -> msg::TransformStamped {
     //let m_transform = to_transform(transform);
     let inverse = transforms::invert_transform(transform.transform);
 
-    let inv = msg::TransformStamped {
+    msg::TransformStamped {
         child_frame_id: transform.header.frame_id,
         header: msg::Header {
             frame_id: transform.child_frame_id,
@@ -23,25 +28,99 @@ pub fn get_inverse(transform: msg::TransformStamped) -> msg::TransformStamped {
                 x: inverse.translation.x, y: inverse.translation.y, z: inverse.translation.z
             }
         }
-    };
-    inv
+    }
 }
 
 
-pub fn to_transform_stamped(transform: msg::Transform, from: std::string::String, to: std::string::String, time: rosrust::Time) -> msg::TransformStamped {
-    let transform_stamped_msg = msg::TransformStamped {
+pub fn to_transform_stamped(transform: msg::Transform, from: std::string::String, to: std::string::String, time: Time) -> msg::TransformStamped {
+    msg::TransformStamped {
         child_frame_id: to,
         header: msg::Header {
             frame_id: from,
             stamp: time,
             seq: 0
         },
-        transform: transform
-    };
-    transform_stamped_msg
+        transform
+    }
 }
 
 
-pub fn get_nanos(dur: rosrust::Duration) -> i64 {
+pub fn get_nanos(dur: Duration) -> i64 {
     i64::from(dur.sec) * 1_000_000_000 + i64::from(dur.nsec)
-}
\ No newline at end of file
+}
+
+
+/// How far from unit length the norm of an incoming quaternion can be before it is normalized.
+const QUATERNION_NORMALIZATION_TOLERANCE: f64 = 1e-5;
+
+/// Checks that the values of a transform are usable.
+///
+/// Transforms with non-finite values or a zero-norm quaternion are refused. A quaternion off unit
+/// length by more than `QUATERNION_NORMALIZATION_TOLERANCE` is normalized with a warning.
+pub fn validate_transform_values(transform: &mut msg::Transform) -> Result<(), TfError> {
+    let msg::Vector3{x, y, z} = transform.translation;
+    if !(x.is_finite() && y.is_finite() && z.is_finite()) {
+        return Err(TfError::InvalidArgument(format!("translation ({}, {}, {}) is not finite", x, y, z)));
+    }
+    let msg::Quaternion{x, y, z, w} = transform.rotation;
+    if !(x.is_finite() && y.is_finite() && z.is_finite() && w.is_finite()) {
+        return Err(TfError::InvalidArgument(format!("quaternion ({}, {}, {}, {}) is not finite", x, y, z, w)));
+    }
+    let norm = (x * x + y * y + z * z + w * w).sqrt();
+    if norm < f64::EPSILON {
+        return Err(TfError::InvalidArgument(format!("quaternion ({}, {}, {}, {}) has a zero norm", x, y, z, w)));
+    }
+    if (norm - 1.0).abs() > QUATERNION_NORMALIZATION_TOLERANCE {
+        log::warn!("Quaternion ({}, {}, {}, {}) is not normalized, its norm is {}", x, y, z, w, norm);
+        transform.rotation = msg::Quaternion{x: x / norm, y: y / norm, z: z / norm, w: w / norm};
+    }
+    Ok(())
+}
+
+/// Checks that a frame id can be used as a key in the buffer.
+///
+/// A leading slash is stripped with a warning, as tf2 does. Empty ids and ids containing
+/// whitespace are refused.
+pub fn validate_frame_id(frame_id: &str) -> Result<&str, TfError> {
+    let stripped = match frame_id.strip_prefix('/') {
+        Some(stripped) => {
+            log::warn!("Frame id {} has a leading slash which tf2 no longer supports, it is stripped", frame_id);
+            stripped
+        },
+        None => frame_id
+    };
+    if stripped.is_empty() {
+        return Err(TfError::InvalidArgument("frame id is empty".to_string()));
+    }
+    if stripped.contains(char::is_whitespace) {
+        return Err(TfError::InvalidArgument(format!("frame id \"{}\" contains whitespace", frame_id)));
+    }
+    Ok(stripped)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn transform_with_norm(norm: f64) -> msg::Transform {
+        msg::Transform {
+            translation: msg::Vector3{x: 0f64, y: 0f64, z: 0f64},
+            rotation: msg::Quaternion{x: 0f64, y: 0f64, z: 0f64, w: norm}
+        }
+    }
+
+    /// Tests that quaternions are normalized only when their norm is off by more than 1e-5
+    #[test]
+    fn test_quaternion_normalization_tolerance() {
+        for norm in [1f64 + 0.99e-5, 1f64 - 0.99e-5] {
+            let mut transform = transform_with_norm(norm);
+            validate_transform_values(&mut transform).unwrap();
+            assert_eq!(transform.rotation.w, norm);
+        }
+        for norm in [1f64 + 1.01e-5, 1f64 - 1.01e-5] {
+            let mut transform = transform_with_norm(norm);
+            validate_transform_values(&mut transform).unwrap();
+            assert_eq!(transform.rotation.w, 1f64);
+        }
+    }
+}